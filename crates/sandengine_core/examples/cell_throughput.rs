@@ -0,0 +1,122 @@
+//! Runs the scenarios of performance.md without a window, timing the simulation, the rendering of chunk images and other passes over all cells
+//! Run with `cargo run --release -p sandengine_core --example cell_throughput`
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use bevy::math::{IVec2, UVec2};
+use rand::SeedableRng;
+use sandengine_core::{
+    cell::Cell,
+    material::{MaterialDefinitions, MaterialId, MaterialRegistry},
+    random::SimulationRng,
+    world::PixelWorld,
+};
+
+const WORLD_SIZE: UVec2 = UVec2::new(512, 512);
+const CHUNK_AMOUNT: UVec2 = UVec2::new(8, 8);
+const STEPS: u32 = 200;
+
+// Sand and water as they are in the default materials of the game
+const MATERIALS: &[u8] = br#"(materials: [
+    (
+        name: "Sand",
+        color: (230, 195, 92, 255),
+        color_noise: 20,
+        physics: SoftSolid,
+        density: 1.6,
+        conductivity: 0.3,
+    ),
+    (
+        name: "Water",
+        color: (20, 125, 205, 150),
+        color_noise: 20,
+        physics: Liquid,
+        density: 1.0,
+        conductivity: 0.6,
+        dispersion: 6,
+    ),
+])"#;
+
+fn main() {
+    let definitions = MaterialDefinitions::from_ron(MATERIALS).expect("materials should parse");
+    let materials = MaterialRegistry::from_definitions(&definitions);
+    let sand = materials.id("Sand").expect("Sand is defined above");
+    let water = materials.id("Water").expect("Water is defined above");
+
+    run("No sand", &materials, |_| {});
+    run("Lots of sand", &materials, |world| {
+        fill(
+            world,
+            &materials,
+            sand,
+            IVec2::new(0, 256),
+            IVec2::new(512, 512),
+        )
+    });
+    run("Constant movement of water", &materials, |world| {
+        fill(world, &materials, water, IVec2::ZERO, IVec2::new(128, 256))
+    });
+}
+
+fn fill(
+    world: &mut PixelWorld,
+    materials: &MaterialRegistry,
+    material: MaterialId,
+    min: IVec2,
+    max: IVec2,
+) {
+    let mut rng = SimulationRng::seed_from_u64(0);
+    for y in min.y..max.y {
+        for x in min.x..max.x {
+            world.set_cell(IVec2::new(x, y), Cell::new(material, materials, &mut rng));
+        }
+    }
+}
+
+// Prints the median time of a simulation step, and of rendering every chunk once the steps are done
+// Also times the passes over every cell of every chunk that run each step and each time colliders are rebuilt
+fn run(name: &str, materials: &MaterialRegistry, setup: impl Fn(&mut PixelWorld)) {
+    let mut world = PixelWorld::new(WORLD_SIZE, CHUNK_AMOUNT, 0);
+    setup(&mut world);
+
+    let step = median_time(|| world.step(materials));
+
+    let chunk_size = world.chunk_size;
+    let mut data = vec![0; (chunk_size.x * chunk_size.y * 4) as usize];
+    let positions: Vec<IVec2> = world.chunks.positions().collect();
+    let render = median_time(|| {
+        for position in &positions {
+            world.render_chunk(*position, &mut data, materials);
+        }
+    });
+    let commit = median_time(|| {
+        for chunk in world.chunks.iter_mut() {
+            chunk.commit_cells_unupdated();
+        }
+    });
+    let floats = median_time(|| {
+        for chunk in world.chunks.iter() {
+            black_box(chunk.cells_as_floats());
+        }
+    });
+
+    println!(
+        "{name}: step {step:?}, rendering all chunks {render:?}, commit_cells_unupdated {commit:?}, cells_as_floats {floats:?}"
+    );
+}
+
+// The median is used rather than the mean, as it is not thrown off by the odd run that gets interrupted
+fn median_time(mut f: impl FnMut()) -> Duration {
+    let mut times: Vec<Duration> = (0..STEPS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    times[times.len() / 2]
+}
@@ -0,0 +1,218 @@
+use rand::Rng;
+use serde::Deserialize;
+use strum::{EnumIter, FromRepr};
+
+use super::material::{MaterialId, MaterialRegistry};
+
+// Temperature of the air, empty cells always have this temperature
+pub const AMBIENT_TEMPERATURE: Temperature = Temperature(20 * Temperature::STEPS_PER_DEGREE);
+
+// Amount of bytes a cell takes up when encoded
+pub const ENCODED_CELL_SIZE: usize = 14;
+
+// A cell of the pixel simulation with physics based on its material
+// Cells are kept to 12 bytes, colors are looked up from the material when chunks are rendered
+#[derive(Clone, Copy, Debug)]
+pub struct Cell {
+    pub material: MaterialId,
+
+    // Variation of the material's color, see `MaterialRegistry::cell_color`
+    pub shade: u8,
+
+    pub physics: PhysicsType,
+
+    pub temperature: Temperature,
+
+    // Remaining updates of a cell that burns out such as fire, 0 if the cell does not burn out
+    pub lifetime: u16,
+
+    // Velocity in cells per update, it builds up while falling and turns sideways when the cell hits something
+    pub velocity: CellVelocity,
+
+    pub updated: bool,
+}
+
+const _: () = assert!(std::mem::size_of::<Cell>() == 12);
+
+// Temperature in sixteenths of a degree Celsius, which covers -2048 to 2047 degrees
+// Heat is exchanged in whole steps so that it is conserved exactly
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Temperature(pub i16);
+
+impl Temperature {
+    pub const STEPS_PER_DEGREE: i16 = 16;
+
+    // Temperatures outside of the range are clamped to it
+    pub fn from_celsius(celsius: f32) -> Self {
+        Self((celsius * Self::STEPS_PER_DEGREE as f32).round() as i16)
+    }
+
+    pub fn celsius(self) -> f32 {
+        self.0 as f32 / Self::STEPS_PER_DEGREE as f32
+    }
+}
+
+// Velocity of a cell in cells per update, which stays far below the limits of i8
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct CellVelocity {
+    pub x: i8,
+    pub y: i8,
+}
+
+impl CellVelocity {
+    pub const ZERO: Self = Self::new(0, 0);
+
+    pub const fn new(x: i8, y: i8) -> Self {
+        Self { x, y }
+    }
+
+    pub fn signum(self) -> Self {
+        Self::new(self.x.signum(), self.y.signum())
+    }
+}
+
+// Different types of physics (movement) behaviors, each material has one of these
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, FromRepr, Default, Deserialize)]
+#[repr(u8)]
+pub enum PhysicsType {
+    #[default]
+    Empty,
+    // Soft solid, like sand that can move
+    SoftSolid,
+    // Hard solid, like stone that can't move
+    HardSolid,
+    // Liquid type such as water
+    Liquid,
+    // Gas type such as as smoke
+    Gas,
+    // Fire which burns in place, spreading to flammable neighbors until its lifetime is used up
+    Fire,
+    // Special case for rigid bodies which don't use cell physics but still contain cells
+    RigidBody,
+}
+
+impl PhysicsType {
+    // Hard and soft solids, which can hold up hard solids resting on them
+    pub fn is_solid(self) -> bool {
+        matches!(self, PhysicsType::HardSolid | PhysicsType::SoftSolid)
+    }
+}
+
+impl Cell {
+    // The random number generator picks the shade of the cell
+    pub fn new(material: MaterialId, materials: &MaterialRegistry, rng: &mut impl Rng) -> Self {
+        let mat = materials.get(material);
+        Self {
+            material,
+            shade: rng.gen(),
+            physics: mat.physics,
+            temperature: mat.temperature,
+            lifetime: mat.lifetime,
+            velocity: CellVelocity::ZERO,
+            updated: false,
+        }
+    }
+
+    pub fn object() -> Self {
+        Self {
+            material: MaterialId::EMPTY,
+            shade: 0,
+            physics: PhysicsType::RigidBody,
+            temperature: AMBIENT_TEMPERATURE,
+            lifetime: 0,
+            velocity: CellVelocity::ZERO,
+            updated: true,
+        }
+    }
+
+    pub fn with_material_and_shade_rigidbody(material: MaterialId, shade: u8) -> Self {
+        Self {
+            material,
+            shade,
+            physics: PhysicsType::RigidBody,
+            temperature: AMBIENT_TEMPERATURE,
+            lifetime: 0,
+            velocity: CellVelocity::ZERO,
+            updated: false,
+        }
+    }
+
+    // Moves the cell over to a reloaded registry, cells of materials which were removed are emptied
+    // Cells of a material whose physics changed start over like a newly placed cell, keeping their shade
+    // Rigid body cells stay in place without a material, as their body still covers them
+    pub fn remap_material(&mut self, remap: &[MaterialId], materials: &MaterialRegistry) {
+        let material = self.material.remap(remap);
+        if self.physics == PhysicsType::RigidBody {
+            self.material = material;
+            return;
+        }
+        if material == MaterialId::EMPTY {
+            *self = Cell::default();
+            return;
+        }
+        let mat = materials.get(material);
+        if mat.physics == self.physics {
+            self.material = material;
+            return;
+        }
+        *self = Self {
+            material,
+            shade: self.shade,
+            physics: mat.physics,
+            temperature: mat.temperature,
+            lifetime: mat.lifetime,
+            velocity: CellVelocity::ZERO,
+            updated: false,
+        };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.physics == PhysicsType::Empty
+    }
+
+    // Writes the cell into bytes, the updated flag is only meaningful during an update so it is left out
+    pub fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(self.material.0.to_le_bytes());
+        bytes.push(self.shade);
+        bytes.push(self.physics as u8);
+        bytes.extend(self.temperature.celsius().to_le_bytes());
+        bytes.extend(self.lifetime.to_le_bytes());
+        bytes.extend((self.velocity.x as i16).to_le_bytes());
+        bytes.extend((self.velocity.y as i16).to_le_bytes());
+    }
+
+    // Reads a cell written by `encode`, returns None if the bytes are too short or not a cell
+    // Temperatures and velocities are written wider than cells hold them, they are clamped when read back
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; ENCODED_CELL_SIZE] = bytes.get(..ENCODED_CELL_SIZE)?.try_into().ok()?;
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        Some(Self {
+            material: MaterialId(u16_at(0)),
+            shade: bytes[2],
+            physics: PhysicsType::from_repr(bytes[3])?,
+            temperature: Temperature::from_celsius(f32::from_le_bytes([
+                bytes[4], bytes[5], bytes[6], bytes[7],
+            ])),
+            lifetime: u16_at(8),
+            velocity: CellVelocity::new(
+                (u16_at(10) as i16).clamp(i8::MIN as i16, i8::MAX as i16) as i8,
+                (u16_at(12) as i16).clamp(i8::MIN as i16, i8::MAX as i16) as i8,
+            ),
+            updated: false,
+        })
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            material: MaterialId::EMPTY,
+            shade: 0,
+            physics: PhysicsType::Empty,
+            temperature: AMBIENT_TEMPERATURE,
+            lifetime: 0,
+            velocity: CellVelocity::ZERO,
+            updated: false,
+        }
+    }
+}
@@ -1,8 +1,9 @@
 use bevy::math::{IVec2, UVec2};
 
 use super::{
-    cell::{Cell, PhysicsType},
+    cell::{Cell, PhysicsType, ENCODED_CELL_SIZE},
     geometry_helpers::BoundRect,
+    material::MaterialRegistry,
 };
 
 // Single chunk of the pixel world
@@ -40,6 +41,40 @@ impl PixelChunk {
         }
     }
 
+    // Creates a chunk from cells written by `encode_cells`, returns None if they do not fill a chunk of this size
+    // The whole chunk starts out dirty, so it is simulated and rendered again
+    pub fn from_encoded_cells(size: UVec2, position: IVec2, bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != (size.x * size.y) as usize * ENCODED_CELL_SIZE {
+            return None;
+        }
+        let mut chunk = Self::new(size, position);
+        for (cell, bytes) in chunk
+            .cells
+            .iter_mut()
+            .zip(bytes.chunks_exact(ENCODED_CELL_SIZE))
+        {
+            *cell = Cell::decode(bytes)?;
+        }
+        Some(chunk)
+    }
+
+    // Writes all cells of the chunk into bytes, row by row starting at the bottom
+    pub fn encode_cells(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.cells.len() * ENCODED_CELL_SIZE);
+        for cell in &self.cells {
+            cell.encode(&mut bytes);
+        }
+        bytes
+    }
+
+    // Marks the whole chunk as dirty so that it is simulated again
+    pub fn wake(&mut self) {
+        self.current_dirty_rect = BoundRect {
+            min: IVec2::ZERO,
+            max: (self.size - UVec2::ONE).as_ivec2(),
+        };
+    }
+
     pub fn should_update(&self) -> bool {
         !self.current_dirty_rect.is_empty() || self.render_override > 0
     }
@@ -80,6 +115,20 @@ impl PixelChunk {
         }
     }
 
+    // Empties the cells past the extent, which are outside of the world
+    pub fn clear_outside(&mut self, extent: UVec2) {
+        if extent == self.size {
+            return;
+        }
+        for (index, cell) in self.cells.iter_mut().enumerate() {
+            let x = index as u32 % self.size.x;
+            let y = index as u32 / self.size.x;
+            if x >= extent.x || y >= extent.y {
+                *cell = Cell::default();
+            }
+        }
+    }
+
     pub fn swap_rects(&mut self) {
         self.previous_dirty_rect = self.current_dirty_rect.union(&self.previous_dirty_rect);
         self.current_dirty_rect = BoundRect::empty();
@@ -108,11 +157,11 @@ impl PixelChunk {
             .collect::<Vec<f64>>()
     }
 
-    // Convert the grid to a byte array for rendering
-    pub fn render_chunk(&self) -> Vec<u8> {
-        self.cells
-            .iter()
-            .flat_map(|cell| cell.color)
-            .collect::<Vec<u8>>()
+    // Writes the colors of the cells into the RGBA bytes of an image the size of the chunk
+    pub fn render_chunk(&self, data: &mut [u8], materials: &MaterialRegistry) {
+        for (pixel, cell) in data.chunks_exact_mut(4).zip(&self.cells) {
+            let pixel: &mut [u8; 4] = pixel.try_into().unwrap();
+            *pixel = materials.cell_color(cell);
+        }
     }
 }
@@ -0,0 +1,197 @@
+//! Storage of the loaded chunks of a world, every chunk keeps the same index for as long as it is loaded
+//! Fixed size worlds use a dense grid, where the index of a chunk is worked out from its position without any lookup
+//! Streaming worlds load chunks at any position, they switch to a sparse grid which looks up indices in a map and reuses the indices of unloaded chunks
+
+use bevy::{
+    math::{IVec2, UVec2},
+    utils::hashbrown::HashMap,
+};
+
+use super::chunk::PixelChunk;
+
+// Finds the index of a chunk position
+pub(crate) enum ChunkIndices {
+    // Chunks from the origin up to the size, row by row
+    Dense {
+        size: UVec2,
+    },
+    // Index of every loaded position, along with the indices that are free to reuse
+    Sparse {
+        indices: HashMap<IVec2, usize>,
+        free: Vec<usize>,
+    },
+}
+
+impl ChunkIndices {
+    // Index a chunk at the position has or would get, dense grids have no index for positions outside of them
+    #[inline]
+    pub fn index(&self, position: IVec2) -> Option<usize> {
+        match self {
+            ChunkIndices::Dense { size } => {
+                let size = size.as_ivec2();
+                (position.cmpge(IVec2::ZERO).all() && position.cmplt(size).all())
+                    .then(|| (position.y * size.x + position.x) as usize)
+            }
+            ChunkIndices::Sparse { indices, .. } => indices.get(&position).copied(),
+        }
+    }
+}
+
+// Loaded chunks of a world by index
+pub struct ChunkGrid {
+    chunks: Vec<Option<PixelChunk>>,
+    indices: ChunkIndices,
+    loaded: usize,
+}
+
+impl ChunkGrid {
+    // Grid which can hold the chunks from the origin up to the amount
+    pub fn dense(chunk_amount: UVec2) -> Self {
+        Self {
+            chunks: (0..chunk_amount.x * chunk_amount.y).map(|_| None).collect(),
+            indices: ChunkIndices::Dense { size: chunk_amount },
+            loaded: 0,
+        }
+    }
+
+    // Grid which can hold chunks at any position
+    pub fn sparse() -> Self {
+        Self {
+            chunks: Vec::new(),
+            indices: ChunkIndices::Sparse {
+                indices: HashMap::new(),
+                free: Vec::new(),
+            },
+            loaded: 0,
+        }
+    }
+
+    // Lets the grid hold chunks at any position, the chunks which are loaded keep their indices
+    pub fn make_sparse(&mut self) {
+        if self.is_sparse() {
+            return;
+        }
+        let mut indices = HashMap::new();
+        let mut free = Vec::new();
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            match chunk {
+                Some(chunk) => {
+                    indices.insert(chunk.position, index);
+                }
+                None => free.push(index),
+            }
+        }
+        // Lowest indices are reused first
+        free.reverse();
+        self.indices = ChunkIndices::Sparse { indices, free };
+    }
+
+    pub fn is_sparse(&self) -> bool {
+        matches!(self.indices, ChunkIndices::Sparse { .. })
+    }
+
+    // Returns true if a chunk at the position can be inserted, which is always the case for sparse grids
+    pub fn can_hold(&self, position: IVec2) -> bool {
+        self.is_sparse() || self.indices.index(position).is_some()
+    }
+
+    // Index of the chunk at the position, if it is loaded
+    #[inline]
+    pub fn index(&self, position: IVec2) -> Option<usize> {
+        let index = self.indices.index(position)?;
+        self.chunks[index].as_ref().map(|_| index)
+    }
+
+    #[inline]
+    pub fn get(&self, position: IVec2) -> Option<&PixelChunk> {
+        self.chunks[self.indices.index(position)?].as_ref()
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, position: IVec2) -> Option<&mut PixelChunk> {
+        self.chunks[self.indices.index(position)?].as_mut()
+    }
+
+    pub fn contains(&self, position: IVec2) -> bool {
+        self.get(position).is_some()
+    }
+
+    pub fn by_index(&self, index: usize) -> Option<&PixelChunk> {
+        self.chunks.get(index)?.as_ref()
+    }
+
+    pub fn by_index_mut(&mut self, index: usize) -> Option<&mut PixelChunk> {
+        self.chunks.get_mut(index)?.as_mut()
+    }
+
+    // Inserts a chunk at its position, replacing the chunk that was there
+    // Returns the index of the chunk, or None if a dense grid can't hold it, in which case it is dropped
+    pub fn insert(&mut self, chunk: PixelChunk) -> Option<usize> {
+        let index = match &mut self.indices {
+            ChunkIndices::Dense { .. } => self.indices.index(chunk.position)?,
+            ChunkIndices::Sparse { indices, free } => match indices.get(&chunk.position) {
+                Some(index) => *index,
+                None => {
+                    let index = free.pop().unwrap_or_else(|| {
+                        self.chunks.push(None);
+                        self.chunks.len() - 1
+                    });
+                    indices.insert(chunk.position, index);
+                    index
+                }
+            },
+        };
+        if self.chunks[index].replace(chunk).is_none() {
+            self.loaded += 1;
+        }
+        Some(index)
+    }
+
+    // Removes the chunk at the position, its index may be given to a chunk inserted later
+    pub fn remove(&mut self, position: IVec2) -> Option<PixelChunk> {
+        let index = match &mut self.indices {
+            ChunkIndices::Dense { .. } => self.indices.index(position)?,
+            ChunkIndices::Sparse { indices, free } => {
+                let index = indices.remove(&position)?;
+                free.push(index);
+                index
+            }
+        };
+        let chunk = self.chunks[index].take()?;
+        self.loaded -= 1;
+        Some(chunk)
+    }
+
+    // Amount of loaded chunks
+    pub fn len(&self) -> usize {
+        self.loaded
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.loaded == 0
+    }
+
+    // Every index is below this, for storing data of chunks by index
+    pub fn index_bound(&self) -> usize {
+        self.chunks.len()
+    }
+
+    // Loaded chunks in the order of their indices
+    pub fn iter(&self) -> impl Iterator<Item = &PixelChunk> {
+        self.chunks.iter().flatten()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut PixelChunk> {
+        self.chunks.iter_mut().flatten()
+    }
+
+    // Positions of the loaded chunks in the order of their indices
+    pub fn positions(&self) -> impl Iterator<Item = IVec2> + '_ {
+        self.iter().map(|chunk| chunk.position)
+    }
+
+    // Lookup of indices alongside the chunks, so the chunks can be borrowed mutably while positions are looked up
+    pub(crate) fn split_mut(&mut self) -> (&ChunkIndices, &mut [Option<PixelChunk>]) {
+        (&self.indices, &mut self.chunks)
+    }
+}
@@ -0,0 +1,999 @@
+use bevy::{
+    math::{IVec2, UVec2},
+    utils::hashbrown::HashMap,
+};
+use rand::Rng;
+
+use super::{
+    cell::{Cell, CellVelocity, PhysicsType, Temperature, AMBIENT_TEMPERATURE},
+    explosion::Explosion,
+    geometry_helpers::{
+        BoundRect, DIRECTIONS, NEIGHBORS, VEC_DOWN, VEC_DOWN_LEFT, VEC_DOWN_RIGHT, VEC_LEFT,
+        VEC_RIGHT, VEC_UP,
+    },
+    material::{MaterialId, MaterialRegistry},
+    neighborhood::ChunkNeighborhood,
+    random::SimulationRng,
+};
+
+// Fraction of the temperature difference between two fully conductive cells that is exchanged every update
+const HEAT_TRANSFER_RATE: f32 = 0.125;
+// Conductivity between a cell and the air of an empty neighbor
+const AIR_CONDUCTIVITY: f32 = 0.01;
+// Velocity gained by falling cells each update
+const GRAVITY: i8 = 1;
+// Fastest speed of falling cells in cells per update
+const TERMINAL_VELOCITY: i8 = 8;
+// Fastest speed of rising gases in cells per update
+const GAS_TERMINAL_VELOCITY: i8 = 2;
+// Chance of a liquid or gas moving straight down (or up) when it could also spread sideways
+const FALL_CHANCE: f64 = 0.95;
+// Chance each update that a gas drifts in a random direction instead of rising
+const DRIFT_CHANCE: f64 = 0.3;
+// Chance each update that a burning cell gives off its emitted material into an empty cell above it
+const EMIT_CHANCE: f64 = 0.1;
+
+// Heat flowing in one update from a cell at `from` into a cell at `to`, in whole temperature steps
+// Flows smaller than a step are dropped, so chunks can go to sleep once temperatures settle
+fn heat_flow(from: Temperature, to: Temperature, conductivity: f32) -> i16 {
+    ((from.0 as i32 - to.0 as i32) as f32 * conductivity * HEAT_TRANSFER_RATE) as i16
+}
+
+// SimulationChunkContext manages a 3x3 group of chunks temporarily while the updates happen
+// It contains functions to help translate positions while updating and dealing with updating neighboring chunk data
+pub struct SimulationChunkContext<'a> {
+    // Position of the center chunk in the world's chunk map
+    pub center_position: IVec2,
+    // The nine chunks, including the center chunk (in the fourth position), only the parts of them in reach of the center can be accessed
+    neighborhood: ChunkNeighborhood<'a>,
+
+    // Dirty rect of the center chunk, which is the area that gets simulated
+    center_rect: BoundRect,
+
+    // Cells at or past this position are outside of the world and can't be reached, in the coordinates of the center chunk
+    // This is where the world ends when its size is not a multiple of the chunk size
+    world_end: IVec2,
+
+    // List of updated positions for each chunk
+    pub dirty_updates: HashMap<IVec2, Vec<IVec2>>,
+
+    // Explosions set off by explosive cells catching fire, in world positions
+    pub explosions: Vec<Explosion>,
+
+    // Solid cells destroyed by burning, reactions and phase changes, in world positions
+    pub removed_solids: Vec<IVec2>,
+
+    chunk_size: UVec2,
+
+    materials: &'a MaterialRegistry,
+
+    // Generator of this chunk for this update, all randomness of the simulation comes from here
+    rng: SimulationRng,
+}
+
+impl SimulationChunkContext<'_> {
+    // Create the contex using the 3x3 chunks around the center chunk
+    pub(crate) fn new<'a>(
+        center_position: IVec2,
+        neighborhood: ChunkNeighborhood<'a>,
+        center_rect: BoundRect,
+        world_end: IVec2,
+        chunk_size: UVec2,
+        materials: &'a MaterialRegistry,
+        rng: SimulationRng,
+    ) -> SimulationChunkContext<'a> {
+        let mut dirty_updates = HashMap::new();
+        for direction in DIRECTIONS {
+            dirty_updates.insert(center_position + direction, Vec::new());
+        }
+        SimulationChunkContext {
+            center_position,
+            neighborhood,
+            // Dirty rects can reach past the end of the world, the cells there are never simulated
+            center_rect: BoundRect {
+                min: center_rect.min,
+                max: center_rect.max.min(world_end - IVec2::ONE),
+            },
+            world_end,
+            dirty_updates,
+            explosions: Vec::new(),
+            removed_solids: Vec::new(),
+            chunk_size,
+            materials,
+            rng,
+        }
+    }
+
+    fn cell_from_index(&self, (chunk, index): (usize, usize)) -> &Cell {
+        self.neighborhood.get(chunk, index).unwrap()
+    }
+
+    // Transforms a 2d position into the 1d index
+    fn cell_index(chunk_width: usize, pos: IVec2) -> usize {
+        pos.x as usize + pos.y as usize * chunk_width
+    }
+
+    fn local_to_indices(&self, pos: IVec2) -> (usize, usize) {
+        // Get the relative chunk position in the 3x3 group
+        let rel_chunk = pos.div_euclid(self.chunk_size.as_ivec2());
+        // Get cell position inside the chunk and convert into 1d position
+        let cell_pos = Self::cell_index(
+            self.chunk_size.x as usize,
+            pos.rem_euclid(self.chunk_size.as_ivec2()),
+        );
+
+        (
+            ((rel_chunk.x + 1) + (rel_chunk.y + 1) * 3) as usize,
+            cell_pos,
+        )
+    }
+
+    // Creates a cell of the material with a shade from this chunk's generator
+    fn new_cell(&mut self, material: MaterialId) -> Cell {
+        Cell::new(material, self.materials, &mut self.rng)
+    }
+
+    fn get_cell(&self, pos: IVec2) -> &Cell {
+        self.cell_from_index(self.local_to_indices(pos))
+    }
+
+    // Gets a cell if the chunk it belongs to exists and the cell is inside of the world
+    fn cell_at(&self, pos: IVec2) -> Option<&Cell> {
+        if pos.cmpge(self.world_end).any() {
+            return None;
+        }
+        let (chunk, index) = self.local_to_indices(pos);
+        self.neighborhood.get(chunk, index)
+    }
+
+    fn cell_is_empty(&self, pos: IVec2) -> bool {
+        self.cell_at(pos)
+            .is_some_and(|cell| cell.is_empty() && !cell.updated)
+    }
+
+    // Checks if the current cell can move into a position, either because it is empty or because it can displace the cell there
+    // Cells sink through liquids and gases which are lighter than them, while gases rise through anything fluid that is heavier
+    fn can_move_into(&self, current: &Cell, pos: IVec2) -> bool {
+        let Some(target) = self.cell_at(pos) else {
+            return false;
+        };
+        if target.updated {
+            return false;
+        }
+        match target.physics {
+            PhysicsType::Empty => true,
+            PhysicsType::Liquid | PhysicsType::Gas => {
+                let current_density = self.materials.get(current.material).density;
+                let target_density = self.materials.get(target.material).density;
+                if current.physics == PhysicsType::Gas {
+                    current_density < target_density
+                } else {
+                    current_density > target_density
+                }
+            }
+            _ => false,
+        }
+    }
+
+    fn set_cell_from_index(&mut self, (chunk, index): (usize, usize), cell: Cell) {
+        *self.neighborhood.get_mut(chunk, index).unwrap() = cell;
+    }
+
+    fn set_updated_cell_from_index(&mut self, (chunk, index): (usize, usize)) {
+        self.neighborhood.get_mut(chunk, index).unwrap().updated = true;
+    }
+
+    fn set_cell(&mut self, pos: IVec2, cell: Cell) {
+        let idx = self.local_to_indices(pos);
+        self.set_cell_from_index(idx, cell);
+        self.mark_dirty(pos);
+    }
+
+    // Sets a cell which turns the cell at the position into another material
+    // Solids which are destroyed this way are noted, whatever they held up may now be floating
+    fn replace_cell(&mut self, pos: IVec2, cell: Cell) {
+        if self.get_cell(pos).physics.is_solid() && !cell.physics.is_solid() {
+            self.note_removed_solid(pos);
+        }
+        self.set_cell(pos, cell);
+    }
+
+    fn note_removed_solid(&mut self, pos: IVec2) {
+        self.removed_solids
+            .push(self.center_position * self.chunk_size.as_ivec2() + pos);
+    }
+
+    fn set_updated_cell(&mut self, pos: IVec2) {
+        let idx = self.local_to_indices(pos);
+        self.set_updated_cell_from_index(idx);
+        self.mark_dirty(pos);
+    }
+
+    // Moves the current cell into the target position
+    // Returns the cell that was there, which takes the place of the current cell
+    fn swap_into(&mut self, current: Cell, target: IVec2) -> Option<Cell> {
+        let displaced = *self.get_cell(target);
+        self.set_cell(target, current);
+        Some(displaced)
+    }
+
+    // Marks the position dirty
+    // If the cell is on the side of its chunk, the adjacent chunk's dirty rect is updated too
+    // Positions are checked relative to the chunk they are in, so this also holds for cells moved into a neighbor
+    fn mark_dirty(&mut self, pos: IVec2) {
+        self.update_dirty_idx(pos);
+        let local = pos.rem_euclid(self.chunk_size.as_ivec2());
+        if local.x == 0 {
+            self.update_dirty_idx(pos + IVec2::X * -1);
+        } else if local.x == self.chunk_size.x as i32 - 1 {
+            self.update_dirty_idx(pos + IVec2::X);
+        }
+        if local.y == 0 {
+            self.update_dirty_idx(pos + IVec2::Y * -1);
+        } else if local.y == self.chunk_size.y as i32 - 1 {
+            self.update_dirty_idx(pos + IVec2::Y);
+        }
+    }
+
+    fn update_dirty_idx(&mut self, pos: IVec2) {
+        let rel_chunk = pos.div_euclid(self.chunk_size.as_ivec2());
+        let chunk_vec = self
+            .dirty_updates
+            .get_mut(&(self.center_position + rel_chunk));
+        if let Some(vec) = chunk_vec {
+            vec.push(pos.rem_euclid(self.chunk_size.as_ivec2()))
+        }
+    }
+
+    // Simulates the chunks based on the center chunk's dirty rect
+    // Returns the updated positions of each chunk, the explosions set off and the solids destroyed while simulating
+    pub fn simulate(&mut self) -> (HashMap<IVec2, Vec<IVec2>>, Vec<Explosion>, Vec<IVec2>) {
+        let center_rect = self.center_rect;
+
+        // Iterate over dirty rect
+        for y in center_rect.min.y..=center_rect.max.y {
+            // Alternate x direction
+            if self.rng.gen_bool(0.5) {
+                for x in center_rect.min.x..=center_rect.max.x {
+                    self.update_cell(IVec2 { x, y });
+                }
+            } else {
+                for x in (center_rect.min.x..=center_rect.max.x).rev() {
+                    self.update_cell(IVec2 { x, y });
+                }
+            }
+        }
+
+        (
+            self.dirty_updates.clone(),
+            std::mem::take(&mut self.explosions),
+            std::mem::take(&mut self.removed_solids),
+        )
+    }
+
+    // Runs all steps of the simulation on a single cell
+    fn update_cell(&mut self, position: IVec2) {
+        self.conduct_heat(position);
+        self.change_phase(position);
+        // Process this cell and set it with the result of the process
+        if let Some(cell) = self.process_cell(position) {
+            self.set_cell(
+                position,
+                Cell {
+                    updated: false,
+                    ..cell
+                },
+            )
+        }
+    }
+
+    // Changes the material of a cell when its temperature crosses one of the material's phase transitions
+    // The new cell keeps the temperature of the old one so that it can change back once it heats up or cools down again
+    fn change_phase(&mut self, position: IVec2) {
+        let current = *self.get_cell(position);
+        if current.physics == PhysicsType::RigidBody {
+            return;
+        }
+        let materials = self.materials;
+        if let Some(into) = materials
+            .get(current.material)
+            .phase_transition(current.temperature)
+        {
+            let cell = self.new_cell(into);
+            self.replace_cell(
+                position,
+                Cell {
+                    temperature: current.temperature,
+                    ..cell
+                },
+            );
+        }
+    }
+
+    // Exchanges heat between a cell and its direct neighbors based on the conductivity of both materials
+    // Each pair of cells exchanges heat once per update, from the cell below or left of the other, so heat is conserved
+    // Empty neighbors are air which stays at the ambient temperature
+    // Cells that exchanged heat are marked dirty, keeping the chunk awake while heat is still moving
+    // Cells which were already updated exchanged heat before they moved here, they are skipped so they only do so once
+    fn conduct_heat(&mut self, position: IVec2) {
+        let idx = self.local_to_indices(position);
+        let mut current = *self.cell_from_index(idx);
+        let conductivity = self.materials.get(current.material).conductivity;
+        if current.is_empty() || current.updated || conductivity <= 0. {
+            return;
+        }
+
+        let mut changed = false;
+        for direction in [VEC_UP, VEC_DOWN, VEC_LEFT, VEC_RIGHT] {
+            let pos = position + direction;
+            let Some(&neighbor) = self.cell_at(pos) else {
+                continue;
+            };
+            let forward = direction == VEC_UP || direction == VEC_RIGHT;
+
+            if neighbor.is_empty() {
+                let flow = heat_flow(
+                    AMBIENT_TEMPERATURE,
+                    current.temperature,
+                    conductivity.min(AIR_CONDUCTIVITY),
+                );
+                if flow != 0 {
+                    current.temperature.0 += flow;
+                    changed = true;
+                }
+                continue;
+            }
+
+            let neighbor_conductivity = self.materials.get(neighbor.material).conductivity;
+            let flow = heat_flow(
+                neighbor.temperature,
+                current.temperature,
+                conductivity.min(neighbor_conductivity),
+            );
+            if flow == 0 {
+                continue;
+            }
+            // The neighbor exchanges heat with this cell when it is updated, it is woken up in case it is outside of the dirty rect
+            if !forward {
+                self.update_dirty_idx(pos);
+                changed = true;
+                continue;
+            }
+            current.temperature.0 += flow;
+            self.set_cell_from_index(
+                self.local_to_indices(pos),
+                Cell {
+                    temperature: Temperature(neighbor.temperature.0 - flow),
+                    ..neighbor
+                },
+            );
+            self.update_dirty_idx(pos);
+            changed = true;
+        }
+
+        if changed {
+            self.set_cell_from_index(idx, current);
+            self.update_dirty_idx(position);
+        }
+    }
+
+    // Accelerates the cell downwards and moves it as far as its velocity takes it
+    fn move_down(&mut self, mut current: Cell, position: IVec2) -> Option<Cell> {
+        current.velocity.y = (current.velocity.y - GRAVITY).max(-TERMINAL_VELOCITY);
+        let distance = -current.velocity.y as i32;
+        self.trace_move(current, position, VEC_DOWN, distance)
+    }
+
+    // Accelerates the cell upwards, gases rise slower than other cells fall
+    fn move_up(&mut self, mut current: Cell, position: IVec2) -> Option<Cell> {
+        current.velocity.y = (current.velocity.y + GRAVITY).min(GAS_TERMINAL_VELOCITY);
+        let distance = current.velocity.y as i32;
+        self.trace_move(current, position, VEC_UP, distance)
+    }
+
+    fn move_left_right(
+        &mut self,
+        current: Cell,
+        position: IVec2,
+        move_left: bool,
+        move_right: bool,
+    ) -> Option<Cell> {
+        // Liquids and gases spread as far as their material's dispersion
+        let spread = self.materials.get(current.material).dispersion as i32;
+        if move_left && move_right {
+            let direction = if self.prefers_right(&current) {
+                VEC_RIGHT
+            } else {
+                VEC_LEFT
+            };
+            self.move_sideways(current, position, direction, spread)
+        } else if move_left {
+            self.move_sideways(current, position, VEC_LEFT, spread)
+        } else if move_right {
+            self.move_sideways(current, position, VEC_RIGHT, spread)
+        } else {
+            None
+        }
+    }
+
+    fn move_down_left_right(
+        &mut self,
+        current: Cell,
+        position: IVec2,
+        move_left: bool,
+        move_right: bool,
+    ) -> Option<Cell> {
+        if move_left && move_right {
+            let direction = if self.prefers_right(&current) {
+                VEC_DOWN_RIGHT
+            } else {
+                VEC_DOWN_LEFT
+            };
+            self.move_sideways(current, position, direction, 2)
+        } else if move_left {
+            self.move_sideways(current, position, VEC_DOWN_LEFT, 2)
+        } else if move_right {
+            self.move_sideways(current, position, VEC_DOWN_RIGHT, 2)
+        } else {
+            None
+        }
+    }
+
+    // Follows the sideways velocity of the cell, or chooses a random direction if it has none
+    fn prefers_right(&mut self, current: &Cell) -> bool {
+        match current.velocity.x {
+            0 => self.rng.gen_bool(0.5),
+            x => x > 0,
+        }
+    }
+
+    // Moves sideways as far as the sideways velocity allows, or one up to spread steps without velocity
+    // The sideways velocity decays with each move and follows the direction that was moved in
+    fn move_sideways(
+        &mut self,
+        mut current: Cell,
+        position: IVec2,
+        direction: IVec2,
+        spread: i32,
+    ) -> Option<Cell> {
+        let speed = current.velocity.x.abs();
+        current.velocity.x = direction.x as i8 * (speed - 1).max(0);
+        let distance = (speed as i32).max(self.rng.gen_range(1..=spread.max(1)));
+        self.trace_move(current, position, direction, distance)
+    }
+
+    // Turns the vertical velocity of a cell which hit something into sideways velocity, which makes liquids splash
+    fn impact(&mut self, current: &mut Cell) {
+        if current.velocity.y == 0 {
+            return;
+        }
+        let speed = (current.velocity.y.abs() / 2).max(current.velocity.x.abs());
+        current.velocity.x = if self.prefers_right(current) {
+            speed
+        } else {
+            -speed
+        };
+        current.velocity.y = 0;
+    }
+
+    // Moves the cell up to distance cells in the direction, tracing through empty cells one by one
+    // When the first cell is not empty the cell swaps with it instead, displacing a fluid slows the cell down
+    // The distance is limited to half the chunk size, which keeps the move inside of the 3x3 chunk context
+    // and away from the half of a neighbor chunk that the context on its other side can write to
+    fn trace_move(
+        &mut self,
+        mut current: Cell,
+        position: IVec2,
+        direction: IVec2,
+        distance: i32,
+    ) -> Option<Cell> {
+        let distance = distance.min(self.chunk_size.min_element() as i32 / 2);
+        let mut traveled = 0;
+        while traveled < distance && self.cell_is_empty(position + direction * (traveled + 1)) {
+            traveled += 1;
+        }
+
+        if traveled == 0 {
+            current.velocity = current.velocity.signum();
+            return self.swap_into(current, position + direction);
+        }
+
+        // Set the cells that were passed through as updated
+        for step in 1..traveled {
+            self.set_updated_cell(position + direction * step);
+        }
+        self.swap_into(current, position + direction * traveled)
+    }
+
+    // Burns a fire cell in place, spreading to flammable neighbors and giving off its emitted material
+    // The cell is always set again, which keeps its chunk awake until it burns out
+    fn burn(&mut self, mut current: Cell, position: IVec2) -> Option<Cell> {
+        let materials = self.materials;
+
+        for direction in [VEC_UP, VEC_DOWN, VEC_LEFT, VEC_RIGHT] {
+            let pos = position + direction;
+            let Some(&neighbor) = self.cell_at(pos) else {
+                continue;
+            };
+            let fuel = materials.get(neighbor.material);
+            let Some(fire) = fuel.ignites_into else {
+                continue;
+            };
+            if self.rng.gen::<f32>() < fuel.flammability {
+                // Explosives still catch fire, the blast is applied once all chunks have been simulated
+                if let Some(explosion) = fuel.explosion {
+                    self.explosions.push(Explosion {
+                        center: self.center_position * self.chunk_size.as_ivec2() + pos,
+                        radius: explosion.radius,
+                        force: explosion.force,
+                        undoable: false,
+                    });
+                }
+                // The new fire burns for as long as its fuel lasts
+                let fire = self.new_cell(fire);
+                self.replace_cell(
+                    pos,
+                    Cell {
+                        lifetime: fuel.burn_time,
+                        updated: true,
+                        ..fire
+                    },
+                );
+            }
+        }
+
+        let material = materials.get(current.material);
+        if let Some(emits) = material.emits {
+            if self.rng.gen_bool(EMIT_CHANCE) && self.cell_is_empty(position + VEC_UP) {
+                let emitted = self.new_cell(emits);
+                self.set_cell(
+                    position + VEC_UP,
+                    Cell {
+                        updated: true,
+                        ..emitted
+                    },
+                );
+            }
+        }
+
+        current.lifetime = current.lifetime.saturating_sub(1);
+        if current.lifetime == 0 {
+            return Some(Cell::default());
+        }
+        // Flicker by picking a new shade each update
+        current.shade = self.rng.gen();
+        Some(current)
+    }
+
+    // Counts down the lifetime of a gas, its color fades out as it runs out
+    // Returns true once the gas is gone, gases without a lifetime last forever
+    fn fade(&self, current: &mut Cell) -> bool {
+        if current.lifetime == 0 {
+            return false;
+        }
+        current.lifetime -= 1;
+        current.lifetime == 0
+    }
+
+    // Checks the reactions of the current cell's material against its 8 neighbors
+    // The first reaction that happens changes both cells, the neighbor is set directly and the new current cell is returned
+    fn react(&mut self, current: Cell, position: IVec2) -> Option<Cell> {
+        let materials = self.materials;
+        let reactions = &materials.get(current.material).reactions;
+        if reactions.is_empty() {
+            return None;
+        }
+
+        for direction in NEIGHBORS {
+            let pos = position + direction;
+            let Some(&neighbor) = self.cell_at(pos) else {
+                continue;
+            };
+            if neighbor.physics == PhysicsType::RigidBody {
+                continue;
+            }
+            for reaction in reactions
+                .iter()
+                .filter(|reaction| reaction.with == neighbor.material)
+            {
+                if self.rng.gen::<f32>() < reaction.probability {
+                    let other = self.new_cell(reaction.other_into);
+                    self.replace_cell(
+                        pos,
+                        Cell {
+                            updated: true,
+                            ..other
+                        },
+                    );
+                    let into = self.new_cell(reaction.into);
+                    if current.physics.is_solid() && !into.physics.is_solid() {
+                        self.note_removed_solid(position);
+                    }
+                    return Some(into);
+                }
+            }
+        }
+        None
+    }
+
+    // Simulates a single cell, given by it's position in the chunk
+    // Uses the chunk context to manipulate the surroundings
+    fn process_cell(&mut self, position: IVec2) -> Option<Cell> {
+        let mut current = *self.get_cell(position);
+
+        if current.updated {
+            return None;
+        } else {
+            current.updated = true;
+        }
+
+        if let Some(reacted) = self.react(current, position) {
+            return Some(reacted);
+        }
+        let mut new = None;
+
+        match current.physics {
+            PhysicsType::Empty => {}
+            PhysicsType::SoftSolid => {
+                let down_free = self.can_move_into(&current, position + VEC_DOWN);
+                let down_left_free = self.can_move_into(&current, position + VEC_DOWN_LEFT);
+                let down_right_free = self.can_move_into(&current, position + VEC_DOWN_RIGHT);
+
+                if down_free
+                    && (!(down_left_free || down_right_free) || self.rng.gen_range(0..10) != 0)
+                {
+                    new = self.move_down(current, position);
+                } else {
+                    if !down_free {
+                        self.impact(&mut current);
+                    }
+                    new = self.move_down_left_right(
+                        current,
+                        position,
+                        down_left_free,
+                        down_right_free,
+                    );
+                }
+            }
+            PhysicsType::Liquid => {
+                let down_free = self.can_move_into(&current, position + VEC_DOWN);
+                let left_free = self.can_move_into(&current, position + VEC_LEFT);
+                let right_free = self.can_move_into(&current, position + VEC_RIGHT);
+
+                if down_free && (!(left_free || right_free) || self.rng.gen_bool(FALL_CHANCE)) {
+                    new = self.move_down(current, position);
+                } else {
+                    if !down_free {
+                        self.impact(&mut current);
+                    }
+                    let viscosity = self.materials.get(current.material).viscosity;
+                    if (left_free || right_free) && self.rng.gen::<f32>() < viscosity {
+                        // Viscous liquids hold still for a while, setting the cell again keeps it awake to spread later
+                        new = Some(current);
+                    } else {
+                        new = self.move_left_right(current, position, left_free, right_free);
+                    }
+                }
+            }
+            PhysicsType::Gas => {
+                if self.fade(&mut current) {
+                    return Some(Cell::default());
+                }
+
+                let up_free = self.can_move_into(&current, position + VEC_UP);
+                let left_free = self.can_move_into(&current, position + VEC_LEFT);
+                let right_free = self.can_move_into(&current, position + VEC_RIGHT);
+                let drift = NEIGHBORS[self.rng.gen_range(0..NEIGHBORS.len())];
+
+                if self.rng.gen_bool(DRIFT_CHANCE) && self.can_move_into(&current, position + drift)
+                {
+                    new = self.swap_into(current, position + drift);
+                } else if up_free && (!(left_free || right_free) || self.rng.gen_bool(FALL_CHANCE))
+                {
+                    new = self.move_up(current, position);
+                } else {
+                    if !up_free {
+                        self.impact(&mut current);
+                    }
+                    new = self.move_left_right(current, position, left_free, right_free);
+                }
+
+                // A fading gas is set again even when it could not move, keeping it awake until it is gone
+                if new.is_none() && current.lifetime > 0 {
+                    new = Some(Cell {
+                        velocity: CellVelocity::ZERO,
+                        ..current
+                    });
+                }
+            }
+            PhysicsType::Fire => {
+                new = self.burn(current, position);
+            }
+            _ => {}
+        }
+
+        // A cell that could not move comes to rest
+        if new.is_none() && current.velocity != CellVelocity::ZERO {
+            new = Some(Cell {
+                velocity: CellVelocity::ZERO,
+                ..current
+            });
+        }
+        new
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{IVec2, UVec2};
+
+    use crate::{
+        cell::{Cell, PhysicsType, Temperature},
+        material::{MaterialDefinitions, MaterialRegistry},
+        neighborhood::Execution,
+        world::PixelWorld,
+    };
+
+    use super::TERMINAL_VELOCITY;
+
+    fn registry(materials: &str) -> MaterialRegistry {
+        let definitions =
+            MaterialDefinitions::from_ron(format!("(materials: [{materials}])").as_bytes())
+                .unwrap();
+        MaterialRegistry::from_definitions(&definitions)
+    }
+
+    fn world(size: UVec2) -> PixelWorld {
+        let mut world = PixelWorld::new(size, UVec2::new(2, 2), 1);
+        world.execution = Execution::SingleThreaded;
+        world
+    }
+
+    fn fill(
+        world: &mut PixelWorld,
+        materials: &MaterialRegistry,
+        name: &str,
+        min: IVec2,
+        max: IVec2,
+    ) {
+        let material = materials.id(name).unwrap();
+        for y in min.y..max.y {
+            for x in min.x..max.x {
+                world.set_material(IVec2::new(x, y), material, materials);
+            }
+        }
+    }
+
+    fn name_at<'a>(
+        world: &PixelWorld,
+        materials: &'a MaterialRegistry,
+        position: IVec2,
+    ) -> &'a str {
+        &materials
+            .get(world.get_cell(position).unwrap().material)
+            .name
+    }
+
+    // Positions of the cells which are not empty, bottom row first
+    fn occupied(world: &PixelWorld) -> Vec<IVec2> {
+        let size = world.world_size.as_ivec2();
+        (0..size.y)
+            .flat_map(|y| (0..size.x).map(move |x| IVec2::new(x, y)))
+            .filter(|position| !world.get_cell(*position).unwrap().is_empty())
+            .collect()
+    }
+
+    fn heat(world: &mut PixelWorld, position: IVec2, temperature: f32) {
+        let cell = world.get_cell(position).unwrap();
+        world.set_cell(
+            position,
+            Cell {
+                temperature: Temperature::from_celsius(temperature),
+                ..cell
+            },
+        );
+    }
+
+    #[test]
+    fn sand_sinks_through_water() {
+        let materials = registry(
+            r#"(name: "Sand", color: (230, 195, 92, 255), physics: SoftSolid, density: 1.6),
+               (name: "Water", color: (20, 125, 205, 150), physics: Liquid, density: 1.0)"#,
+        );
+        let mut world = world(UVec2::new(16, 16));
+        fill(
+            &mut world,
+            &materials,
+            "Water",
+            IVec2::ZERO,
+            IVec2::new(16, 4),
+        );
+        fill(
+            &mut world,
+            &materials,
+            "Sand",
+            IVec2::new(0, 8),
+            IVec2::new(16, 9),
+        );
+
+        for _ in 0..200 {
+            world.step(&materials);
+        }
+        // The sand has swapped places with the water below it, a few drops can be trapped between the grains
+        let sunk = (0..2)
+            .flat_map(|y| (0..16).map(move |x| IVec2::new(x, y)))
+            .filter(|position| name_at(&world, &materials, *position) == "Sand")
+            .count();
+        assert_eq!(sunk, 16);
+        for x in 0..16 {
+            assert_eq!(name_at(&world, &materials, IVec2::new(x, 4)), "Water");
+            assert!(world.get_cell(IVec2::new(x, 5)).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn boiling_water_turns_into_steam_and_condenses_again() {
+        let materials = registry(
+            r#"(name: "Water", color: (20, 125, 205, 150), physics: Liquid, density: 1.0,
+                transitions: [(when: Above(100.0), into: "Steam")]),
+               (name: "Steam", color: (220, 220, 235, 120), physics: Gas, density: 0.0006,
+                transitions: [(when: Below(90.0), into: "Water")])"#,
+        );
+        let mut world = world(UVec2::new(16, 16));
+        fill(
+            &mut world,
+            &materials,
+            "Water",
+            IVec2::new(8, 2),
+            IVec2::new(9, 3),
+        );
+        heat(&mut world, IVec2::new(8, 2), 150.);
+
+        world.step(&materials);
+        let steam = occupied(&world)[0];
+        let cell = world.get_cell(steam).unwrap();
+        assert_eq!(name_at(&world, &materials, steam), "Steam");
+        assert_eq!(cell.physics, PhysicsType::Gas);
+        // The steam keeps the heat of the water it came from
+        assert!(cell.temperature.celsius() > 140.);
+
+        heat(&mut world, steam, 50.);
+        world.step(&materials);
+        let water = occupied(&world)[0];
+        assert_eq!(name_at(&world, &materials, water), "Water");
+        assert_eq!(world.get_cell(water).unwrap().physics, PhysicsType::Liquid);
+    }
+
+    #[test]
+    fn water_and_lava_react_into_steam_and_stone() {
+        let definitions = MaterialDefinitions::from_ron(
+            br#"(
+                materials: [
+                    (name: "Water", color: (20, 125, 205, 150), physics: Liquid, density: 1.0),
+                    (name: "Lava", color: (255, 90, 20, 255), physics: Liquid, density: 3.1),
+                    (name: "Steam", color: (220, 220, 235, 120), physics: Gas, density: 0.0006),
+                    (name: "Stone", color: (120, 120, 120, 255), physics: HardSolid),
+                ],
+                reactions: [
+                    (a: "Water", b: "Lava", a_into: "Steam", b_into: "Stone", probability: 1.0),
+                ],
+            )"#,
+        )
+        .unwrap();
+        let materials = MaterialRegistry::from_definitions(&definitions);
+        let mut world = world(UVec2::new(16, 16));
+        fill(
+            &mut world,
+            &materials,
+            "Lava",
+            IVec2::new(8, 0),
+            IVec2::new(9, 1),
+        );
+        fill(
+            &mut world,
+            &materials,
+            "Water",
+            IVec2::new(8, 1),
+            IVec2::new(9, 2),
+        );
+
+        world.step(&materials);
+        assert_eq!(name_at(&world, &materials, IVec2::new(8, 0)), "Stone");
+        let names: Vec<&str> = occupied(&world)
+            .into_iter()
+            .map(|position| name_at(&world, &materials, position))
+            .collect();
+        assert_eq!(names, ["Stone", "Steam"]);
+    }
+
+    #[test]
+    fn falling_cells_speed_up() {
+        let materials = registry(
+            r#"(name: "Sand", color: (230, 195, 92, 255), physics: SoftSolid, density: 1.6)"#,
+        );
+        let mut world = world(UVec2::new(64, 128));
+        let start = IVec2::new(30, 120);
+        fill(&mut world, &materials, "Sand", start, start + IVec2::ONE);
+
+        for _ in 0..3 {
+            world.step(&materials);
+        }
+        // Moving a cell per update would have taken it 3 cells down
+        let position = occupied(&world)[0];
+        assert!(start.y - position.y > 3);
+
+        for _ in 0..9 {
+            world.step(&materials);
+        }
+        let position = occupied(&world)[0];
+        assert!(world.get_cell(position).unwrap().velocity.y <= -TERMINAL_VELOCITY / 2);
+        assert!(position.y > 0);
+    }
+
+    #[test]
+    fn viscous_liquids_spread_slower() {
+        let materials = registry(
+            r#"(name: "Water", color: (20, 125, 205, 150), physics: Liquid, density: 1.0, dispersion: 6),
+               (name: "Honey", color: (235, 165, 30, 230), physics: Liquid, density: 1.4, dispersion: 1, viscosity: 0.9)"#,
+        );
+        // Width of the puddle a column of the liquid has spread into
+        let spread = |name: &str| {
+            let mut world = world(UVec2::new(64, 32));
+            fill(
+                &mut world,
+                &materials,
+                name,
+                IVec2::new(32, 0),
+                IVec2::new(33, 12),
+            );
+            for _ in 0..30 {
+                world.step(&materials);
+            }
+            let floor: Vec<i32> = occupied(&world)
+                .into_iter()
+                .filter(|position| position.y == 0)
+                .map(|position| position.x)
+                .collect();
+            floor.iter().max().unwrap() - floor.iter().min().unwrap() + 1
+        };
+        let water = spread("Water");
+        let honey = spread("Honey");
+        assert!(water >= 12);
+        assert!(honey * 2 <= water);
+    }
+
+    #[test]
+    fn gases_fade_out_over_their_lifetime() {
+        let materials = registry(
+            r#"(name: "Smoke", color: (192, 192, 192, 150), physics: Gas, density: 0.001, lifetime: 10),
+               (name: "Air", color: (200, 230, 255, 20), physics: Gas, density: 0.001)"#,
+        );
+        let mut world = world(UVec2::new(32, 32));
+        fill(
+            &mut world,
+            &materials,
+            "Smoke",
+            IVec2::new(8, 4),
+            IVec2::new(9, 5),
+        );
+        fill(
+            &mut world,
+            &materials,
+            "Air",
+            IVec2::new(24, 4),
+            IVec2::new(25, 5),
+        );
+
+        for _ in 0..9 {
+            world.step(&materials);
+        }
+        assert_eq!(occupied(&world).len(), 2);
+        world.step(&materials);
+        // Gases without a lifetime last forever
+        let left: Vec<&str> = occupied(&world)
+            .into_iter()
+            .map(|position| name_at(&world, &materials, position))
+            .collect();
+        assert_eq!(left, ["Air"]);
+    }
+}
@@ -0,0 +1,174 @@
+//! Explosions which blast cells out of the pixel world
+//! Explosive materials queue an explosion when they catch fire, the world only removes the cells and returns the movable ones
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use super::{
+    cell::{Cell, PhysicsType},
+    material::MaterialRegistry,
+    world::PixelWorld,
+};
+
+// Speed of a particle thrown from the center of an explosion per unit of force
+const PARTICLE_SPEED: f32 = 0.3;
+// Largest random change to the direction a particle is thrown in
+const PARTICLE_SPREAD: f32 = 0.3;
+
+// Explosion centered on a cell of the world
+// The force of the blast falls off linearly towards the radius, cells are only broken if it is above their material's strength
+#[derive(Event, Clone, Copy, Debug)]
+pub struct Explosion {
+    pub center: IVec2,
+    pub radius: f32,
+    pub force: f32,
+    // Explosions set off by the player are recorded in the edit history, so they can be undone
+    pub undoable: bool,
+}
+
+// A movable cell removed by an explosion
+pub struct BlastedCell {
+    pub cell: Cell,
+    pub position: Vec2,
+    // Velocity pointing away from the center of the explosion
+    pub velocity: Vec2,
+}
+
+impl PixelWorld {
+    // Removes every cell inside of the explosion which is not strong enough to withstand it
+    // Movable cells are returned so that they can be thrown, other cells are destroyed and explosives are set alight
+    pub fn explode(
+        &mut self,
+        explosion: &Explosion,
+        materials: &MaterialRegistry,
+    ) -> Vec<BlastedCell> {
+        let mut blasted = Vec::new();
+
+        let reach = explosion.radius.ceil() as i32;
+        for y in -reach..=reach {
+            for x in -reach..=reach {
+                let offset = IVec2 { x, y };
+                let distance = offset.as_vec2().length();
+                if distance > explosion.radius {
+                    continue;
+                }
+                let position = explosion.center + offset;
+                let Some(cell) = self.get_cell(position) else {
+                    continue;
+                };
+                // Rigid bodies are pushed by the physics engine instead
+                if matches!(cell.physics, PhysicsType::Empty | PhysicsType::RigidBody) {
+                    continue;
+                }
+                let power = explosion.force * (1. - distance / explosion.radius);
+                let material = materials.get(cell.material);
+                if power <= material.strength {
+                    continue;
+                }
+
+                // Setting explosives on fire instead of destroying them lets explosions chain
+                if let (Some(_), Some(fire)) = (material.explosion, material.ignites_into) {
+                    let fire = Cell::new(fire, materials, self.rng());
+                    self.set_cell(
+                        position,
+                        Cell {
+                            lifetime: material.burn_time.max(1),
+                            ..fire
+                        },
+                    );
+                    continue;
+                }
+
+                self.set_cell(position, Cell::default());
+                if matches!(
+                    cell.physics,
+                    PhysicsType::SoftSolid | PhysicsType::Liquid | PhysicsType::Gas
+                ) {
+                    // The center cell is thrown upwards
+                    let direction = if offset == IVec2::ZERO {
+                        Vec2::Y
+                    } else {
+                        offset.as_vec2().normalize()
+                    };
+                    let spread = Vec2::new(
+                        self.rng().gen_range(-PARTICLE_SPREAD..PARTICLE_SPREAD),
+                        self.rng().gen_range(-PARTICLE_SPREAD..PARTICLE_SPREAD),
+                    );
+                    blasted.push(BlastedCell {
+                        cell,
+                        position: position.as_vec2(),
+                        velocity: (direction + spread) * power * PARTICLE_SPEED,
+                    });
+                }
+            }
+        }
+        blasted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{IVec2, UVec2};
+
+    use crate::{
+        material::{MaterialDefinitions, MaterialRegistry},
+        world::PixelWorld,
+    };
+
+    use super::Explosion;
+
+    #[test]
+    fn explosion_clears_its_radius() {
+        let definitions = MaterialDefinitions::from_ron(
+            br#"(materials: [
+                (name: "Sand", color: (230, 195, 92, 255), physics: SoftSolid, density: 1.6),
+                (name: "Stone", color: (120, 120, 120, 255), physics: HardSolid, strength: 8.0),
+            ])"#,
+        )
+        .unwrap();
+        let materials = MaterialRegistry::from_definitions(&definitions);
+        let sand = materials.id("Sand").unwrap();
+        let stone = materials.id("Stone").unwrap();
+        let mut world = PixelWorld::new(UVec2::new(32, 32), UVec2::new(2, 2), 1);
+        // Sand on the left half and stone on the right half
+        for y in 0..32 {
+            for x in 0..32 {
+                let material = if x < 16 { sand } else { stone };
+                world.set_material(IVec2::new(x, y), material, &materials);
+            }
+        }
+
+        let explosion = Explosion {
+            center: IVec2::new(16, 16),
+            radius: 6.,
+            force: 10.,
+            undoable: false,
+        };
+        let blasted = world.explode(&explosion, &materials);
+
+        let mut cleared = 0;
+        for y in 0..32 {
+            for x in 0..32 {
+                let position = IVec2::new(x, y);
+                let distance = (position - explosion.center).as_vec2().length();
+                let empty = world.get_cell(position).unwrap().is_empty();
+                // The force has fallen off to nothing at the radius
+                if distance >= explosion.radius {
+                    assert!(!empty);
+                } else if x < 16 {
+                    // Sand has no strength, all of it inside of the radius is thrown out
+                    assert!(empty);
+                    cleared += 1;
+                } else if empty {
+                    // Stone only breaks close to the center, where the force is still above its strength
+                    assert!(explosion.force * (1. - distance / explosion.radius) > 8.);
+                    cleared += 1;
+                }
+            }
+        }
+        assert!(cleared > 0);
+        // Only the sand is thrown, the broken stone is destroyed
+        assert!(blasted.iter().all(|blasted| blasted.cell.material == sand));
+        assert!(blasted.len() < cleared);
+    }
+}
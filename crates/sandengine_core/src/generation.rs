@@ -0,0 +1,148 @@
+//! Generation of the cells of new chunks, used for chunks of fixed size worlds and chunks created while streaming
+//! Generators only depend on the world seed and the position of a chunk, so a chunk looks the same no matter when or in which order it is created
+
+use bevy::math::{FloatExt, IVec2, Vec2};
+use rand::SeedableRng;
+
+use super::{
+    cell::Cell,
+    chunk::PixelChunk,
+    material::{MaterialId, MaterialRegistry},
+    random::{splitmix64, SimulationRng},
+};
+
+// Fills new chunks of a world with cells
+pub trait ChunkGenerator: Send + Sync {
+    // Places the cells of a new chunk, which is empty when this is called
+    // The generator is seeded for this chunk, it should be used for anything random such as cell shades
+    fn generate(&self, seed: u64, chunk: &mut PixelChunk, rng: &mut SimulationRng);
+}
+
+impl<G: ChunkGenerator + ?Sized> ChunkGenerator for Box<G> {
+    fn generate(&self, seed: u64, chunk: &mut PixelChunk, rng: &mut SimulationRng) {
+        (**self).generate(seed, chunk, rng);
+    }
+}
+
+// Leaves new chunks empty
+pub struct EmptyGenerator;
+
+impl ChunkGenerator for EmptyGenerator {
+    fn generate(&self, _seed: u64, _chunk: &mut PixelChunk, _rng: &mut SimulationRng) {}
+}
+
+// Generator of a chunk, different for every chunk of a world
+pub(super) fn generation_rng(seed: u64, position: IVec2) -> SimulationRng {
+    let mut rng = SimulationRng::seed_from_u64(splitmix64(!seed));
+    rng.set_stream(((position.x as u32 as u64) << 32) | position.y as u32 as u64);
+    rng
+}
+
+// Rolling terrain of dirt on top of stone, with caves through the stone and pockets of water in some of the caves
+pub struct TerrainGenerator {
+    materials: MaterialRegistry,
+    dirt: MaterialId,
+    stone: MaterialId,
+    water: MaterialId,
+
+    // Height of the surface in world cells, before hills are added
+    pub surface_height: i32,
+    // How far hills rise above and sink below the surface height
+    pub hill_height: f32,
+    // Depth of the dirt layer below the surface, the rest is stone
+    pub dirt_depth: f32,
+    // Noise value above which stone is carved out into caves, from 0 to 1, higher values give fewer caves
+    pub cave_threshold: f32,
+    // Noise value above which caves are filled with water, from 0 to 1
+    pub water_threshold: f32,
+}
+
+impl TerrainGenerator {
+    // Returns None if the registry has no dirt, stone or water material
+    pub fn new(materials: &MaterialRegistry, surface_height: i32) -> Option<Self> {
+        Some(Self {
+            dirt: materials.id("Dirt")?,
+            stone: materials.id("Stone")?,
+            water: materials.id("Water")?,
+            materials: materials.clone(),
+            surface_height,
+            hill_height: 24.,
+            dirt_depth: 10.,
+            cave_threshold: 0.65,
+            water_threshold: 0.6,
+        })
+    }
+
+    // Height of the surface at a column of the world
+    pub fn surface_at(&self, seed: u64, x: i32) -> i32 {
+        let hills = value_noise(seed, Vec2::new(x as f32 / 96., 0.)) * 2. - 1.;
+        let bumps = value_noise(seed ^ 1, Vec2::new(x as f32 / 24., 0.)) * 2. - 1.;
+        self.surface_height + ((hills + bumps / 4.) * self.hill_height) as i32
+    }
+
+    fn material_at(&self, seed: u64, position: IVec2, surface: i32) -> MaterialId {
+        let depth = surface - position.y;
+        if depth < 0 {
+            return MaterialId::EMPTY;
+        }
+
+        // Caves stay below the dirt, so the surface does not get holes in it
+        let point = position.as_vec2();
+        let dirt_depth =
+            self.dirt_depth * (0.5 + value_noise(seed ^ 2, Vec2::new(point.x / 16., 0.)));
+        if depth as f32 > dirt_depth + 4. {
+            let cave = value_noise(seed ^ 3, point / Vec2::new(32., 20.)) * 0.7
+                + value_noise(seed ^ 4, point / 8.) * 0.3;
+            if cave > self.cave_threshold {
+                let pocket = value_noise(seed ^ 5, point / 48.);
+                return if pocket > self.water_threshold {
+                    self.water
+                } else {
+                    MaterialId::EMPTY
+                };
+            }
+        }
+
+        if (depth as f32) < dirt_depth {
+            self.dirt
+        } else {
+            self.stone
+        }
+    }
+}
+
+impl ChunkGenerator for TerrainGenerator {
+    fn generate(&self, seed: u64, chunk: &mut PixelChunk, rng: &mut SimulationRng) {
+        let origin = chunk.position * chunk.size.as_ivec2();
+        for x in 0..chunk.size.x as i32 {
+            let surface = self.surface_at(seed, origin.x + x);
+            for y in 0..chunk.size.y as i32 {
+                let material = self.material_at(seed, origin + IVec2 { x, y }, surface);
+                if material != MaterialId::EMPTY {
+                    let index = chunk.get_index(x, y);
+                    chunk.cells[index] = Cell::new(material, &self.materials, rng);
+                }
+            }
+        }
+    }
+}
+
+// Smooth noise from 0 to 1, interpolating random values placed on the corners of a unit grid
+fn value_noise(seed: u64, point: Vec2) -> f32 {
+    let corner = point.floor();
+    let t = point - corner;
+    // Smoothstep, so the noise has no creases along the grid lines
+    let t = t * t * (3. - 2. * t);
+
+    let corner = corner.as_ivec2();
+    let value = |offset: IVec2| {
+        let position = corner + offset;
+        let hash = splitmix64(
+            seed ^ splitmix64(((position.x as u32 as u64) << 32) | position.y as u32 as u64),
+        );
+        (hash >> 40) as f32 / (1u64 << 24) as f32
+    };
+    let bottom = value(IVec2::ZERO).lerp(value(IVec2::X), t.x);
+    let top = value(IVec2::Y).lerp(value(IVec2::ONE), t.x);
+    bottom.lerp(top, t.y)
+}
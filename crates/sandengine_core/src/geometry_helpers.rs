@@ -21,6 +21,18 @@ pub const DIRECTIONS: [IVec2; 9] = [
     VEC_UP_RIGHT,
 ];
 
+// The 8 directions around a cell
+pub const NEIGHBORS: [IVec2; 8] = [
+    VEC_DOWN_LEFT,
+    VEC_DOWN,
+    VEC_DOWN_RIGHT,
+    VEC_LEFT,
+    VEC_RIGHT,
+    VEC_UP_LEFT,
+    VEC_UP,
+    VEC_UP_RIGHT,
+];
+
 // Like IRect but can be a line
 #[derive(Clone, Copy, Debug)]
 pub struct BoundRect {
@@ -43,7 +55,7 @@ impl BoundRect {
     }
 
     pub fn from_points(points: &[IVec2]) -> Self {
-        if points.len() == 0 {
+        if points.is_empty() {
             return Self::empty();
         }
 
@@ -76,15 +88,15 @@ impl BoundRect {
         let mut new_bound = *self;
 
         if self.is_empty() {
-            return Self {
+            Self {
                 min: *point,
                 max: *point,
-            };
+            }
         } else {
             new_bound.min = IVec2::min(self.min, *point);
             new_bound.max = IVec2::max(self.max, *point);
 
-            return new_bound;
+            new_bound
         }
     }
 
@@ -0,0 +1,332 @@
+//! History of edits made to the world by the player, such as brush strokes and explosions, so they can be undone and redone
+//! Every edit stores the cells it replaced, the first time each cell is touched, and undoing it puts them back
+//! The history keeps to a memory budget by forgetting the oldest edits
+
+use std::collections::VecDeque;
+
+use bevy::{math::IVec2, prelude::Resource, utils::hashbrown::HashSet};
+
+use super::{
+    cell::{Cell, PhysicsType},
+    explosion::{BlastedCell, Explosion},
+    material::{MaterialId, MaterialRegistry},
+    world::PixelWorld,
+};
+
+// Memory used by the history unless set otherwise, in bytes
+pub const DEFAULT_HISTORY_BUDGET: usize = 32 * 1024 * 1024;
+
+// Cells replaced by one edit, each position is only stored once
+#[derive(Default)]
+struct Edit {
+    cells: Vec<(IVec2, Cell)>,
+}
+
+impl Edit {
+    fn memory(&self) -> usize {
+        self.cells.len() * std::mem::size_of::<(IVec2, Cell)>()
+    }
+
+    // Puts the stored cells back into the world, returning the edit that reverts this
+    fn apply(self, world: &mut PixelWorld) -> Edit {
+        let mut reverse = Edit::default();
+        let mut chunks = HashSet::new();
+        for (position, cell) in self.cells {
+            let Some(current) = world.get_cell(position) else {
+                continue;
+            };
+            // Rigid bodies have moved on since, their cells are managed by the physics engine
+            if current.physics == PhysicsType::RigidBody {
+                continue;
+            }
+            let cell = if cell.physics == PhysicsType::RigidBody {
+                Cell::default()
+            } else {
+                cell
+            };
+            reverse.cells.push((position, current));
+            world.set_cell(position, cell);
+            chunks.insert(PixelWorld::cell_to_chunk_position(
+                world.chunk_size,
+                position,
+            ));
+        }
+
+        // Restored cells may need to move again, as may the cells around them
+        for chunk in chunks {
+            world.wake_chunks_around(chunk);
+        }
+        reverse
+    }
+}
+
+// Undo and redo stacks of edits to a pixel world
+// Changes are recorded by making them through the history, and grouped into one edit until `commit` is called
+#[derive(Resource)]
+pub struct EditHistory {
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+
+    // Edit which is being recorded, along with the cells it already stored
+    current: Edit,
+    recorded: HashSet<IVec2>,
+    // Set once the edit being recorded outgrew the budget, the rest of it is not recorded
+    overflowed: bool,
+
+    // Largest amount of memory the history may use in bytes, including the edit being recorded
+    // Edits beyond this are forgotten oldest first, an edit larger than the whole budget can't be undone
+    pub budget: usize,
+    // Memory used by the finished edits
+    used: usize,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_BUDGET)
+    }
+}
+
+impl EditHistory {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            current: Edit::default(),
+            recorded: HashSet::new(),
+            overflowed: false,
+            budget,
+            used: 0,
+        }
+    }
+
+    // Stores the cell at the position in the current edit, unless it was already stored
+    pub fn record(&mut self, world: &PixelWorld, position: IVec2) {
+        if self.overflowed || self.recorded.contains(&position) {
+            return;
+        }
+        if let Some(cell) = world.get_cell(position) {
+            // A new edit means the undone edits can't be redone, their memory is freed for it right away
+            if self.current.cells.is_empty() {
+                for edit in self.redo.drain(..) {
+                    self.used -= edit.memory();
+                }
+            }
+            self.recorded.insert(position);
+            self.current.cells.push((position, cell));
+            self.trim();
+        }
+    }
+
+    // Sets a cell of the world as part of the current edit
+    pub fn set_cell(&mut self, world: &mut PixelWorld, position: IVec2, cell: Cell) {
+        self.record(world, position);
+        world.set_cell(position, cell);
+    }
+
+    // Places a new cell of the material as part of the current edit
+    pub fn set_material(
+        &mut self,
+        world: &mut PixelWorld,
+        position: IVec2,
+        material: MaterialId,
+        materials: &MaterialRegistry,
+    ) {
+        self.record(world, position);
+        world.set_material(position, material, materials);
+    }
+
+    // Sets off an explosion in the world as an edit of its own
+    // Cells which are thrown out are left out of the edit, undoing it would otherwise restore them alongside the particles they became
+    pub fn explode(
+        &mut self,
+        world: &mut PixelWorld,
+        explosion: &Explosion,
+        materials: &MaterialRegistry,
+    ) -> Vec<BlastedCell> {
+        self.commit();
+        let reach = explosion.radius.ceil() as i32;
+        for y in -reach..=reach {
+            for x in -reach..=reach {
+                let offset = IVec2 { x, y };
+                if offset.as_vec2().length() <= explosion.radius {
+                    self.record(world, explosion.center + offset);
+                }
+            }
+        }
+        let blasted = world.explode(explosion, materials);
+        let thrown: HashSet<IVec2> = blasted
+            .iter()
+            .map(|blasted| blasted.position.as_ivec2())
+            .collect();
+        self.current
+            .cells
+            .retain(|(position, _)| !thrown.contains(position));
+        self.commit();
+        blasted
+    }
+
+    // Finishes the current edit, so that it can be undone
+    pub fn commit(&mut self) {
+        self.overflowed = false;
+        if self.current.cells.is_empty() {
+            return;
+        }
+        self.recorded.clear();
+        let edit = std::mem::take(&mut self.current);
+        self.used += edit.memory();
+        self.undo.push_back(edit);
+        self.trim();
+    }
+
+    // Reverts the last edit, returns false if there was nothing to undo
+    pub fn undo(&mut self, world: &mut PixelWorld) -> bool {
+        self.commit();
+        let Some(edit) = self.undo.pop_back() else {
+            return false;
+        };
+        self.used -= edit.memory();
+        let reverse = edit.apply(world);
+        self.used += reverse.memory();
+        self.redo.push(reverse);
+        true
+    }
+
+    // Makes the last undone edit again, returns false if there was nothing to redo
+    pub fn redo(&mut self, world: &mut PixelWorld) -> bool {
+        self.commit();
+        let Some(edit) = self.redo.pop() else {
+            return false;
+        };
+        self.used -= edit.memory();
+        let reverse = edit.apply(world);
+        self.used += reverse.memory();
+        self.undo.push_back(reverse);
+        true
+    }
+
+    // Forgets every edit, such as when the world is replaced
+    pub fn clear(&mut self) {
+        *self = Self::new(self.budget);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() || !self.current.cells.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    // Moves the stored cells over to a reloaded registry, see `MaterialRegistry::remap_from`
+    pub fn remap_materials(&mut self, remap: &[MaterialId], materials: &MaterialRegistry) {
+        let edits = self
+            .undo
+            .iter_mut()
+            .chain(self.redo.iter_mut())
+            .chain(std::iter::once(&mut self.current));
+        for edit in edits {
+            for (_, cell) in edit.cells.iter_mut() {
+                cell.remap_material(remap, materials);
+            }
+        }
+    }
+
+    // Memory used by the history in bytes, including the edit being recorded
+    pub fn memory(&self) -> usize {
+        self.used + self.current.memory()
+    }
+
+    // Forgets the oldest edits until the history fits its budget
+    // An edit being recorded which does not fit on its own is dropped
+    fn trim(&mut self) {
+        if self.current.memory() > self.budget {
+            self.current = Edit::default();
+            self.recorded.clear();
+            self.overflowed = true;
+        }
+        while self.memory() > self.budget {
+            let Some(edit) = self.undo.pop_front() else {
+                break;
+            };
+            self.used -= edit.memory();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{IVec2, UVec2};
+
+    use crate::{
+        material::{MaterialDefinitions, MaterialRegistry},
+        world::PixelWorld,
+    };
+
+    use super::{Edit, EditHistory};
+
+    fn registry() -> MaterialRegistry {
+        let definitions = MaterialDefinitions::from_ron(
+            br#"(materials: [
+                (name: "Stone", color: (120, 120, 120, 255), physics: HardSolid),
+            ])"#,
+        )
+        .unwrap();
+        MaterialRegistry::from_definitions(&definitions)
+    }
+
+    #[test]
+    fn undo_and_redo_restore_the_world() {
+        let materials = registry();
+        let stone = materials.id("Stone").unwrap();
+        let mut world = PixelWorld::new(UVec2::new(32, 32), UVec2::new(2, 2), 1);
+        let mut history = EditHistory::default();
+        let before = world.checksum();
+
+        for x in 4..28 {
+            history.set_material(&mut world, IVec2::new(x, 10), stone, &materials);
+        }
+        history.commit();
+        let after = world.checksum();
+        assert_ne!(before, after);
+
+        assert!(history.undo(&mut world));
+        assert_eq!(world.checksum(), before);
+        assert!(history.redo(&mut world));
+        assert_eq!(world.checksum(), after);
+    }
+
+    #[test]
+    fn edit_being_recorded_counts_against_the_budget() {
+        let materials = registry();
+        let stone = materials.id("Stone").unwrap();
+        let mut world = PixelWorld::new(UVec2::new(64, 64), UVec2::new(2, 2), 1);
+        let cell_memory = Edit {
+            cells: vec![Default::default()],
+        }
+        .memory();
+        let mut history = EditHistory::new(cell_memory * 100);
+
+        // A finished edit, which is forgotten to make room for the stroke being recorded
+        for x in 0..60 {
+            history.set_material(&mut world, IVec2::new(x, 0), stone, &materials);
+        }
+        history.commit();
+        for x in 0..60 {
+            history.set_material(&mut world, IVec2::new(x, 1), stone, &materials);
+            assert!(history.memory() <= history.budget);
+        }
+        history.commit();
+        assert!(history.undo(&mut world));
+        assert!(!history.undo(&mut world));
+
+        // A stroke larger than the whole budget can't be undone
+        for y in 2..6 {
+            for x in 0..60 {
+                history.set_material(&mut world, IVec2::new(x, y), stone, &materials);
+                assert!(history.memory() <= history.budget);
+            }
+        }
+        history.commit();
+        assert!(!history.undo(&mut world));
+    }
+}
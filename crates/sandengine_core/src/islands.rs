@@ -0,0 +1,123 @@
+//! Finds islands of hard solid cells which are no longer connected to the ground
+//! Only the neighbors of solid cells which were destroyed are checked, so the flood fills stay small
+//! Cells are destroyed by edits such as digging, explosions, and in the simulation by burning, reactions and phase changes
+//! Soft solids such as sand hold up hard solids resting on them, fluids, fire and rigid bodies do not
+
+use bevy::{
+    math::{IVec2, UVec2},
+    utils::HashSet,
+};
+
+use super::{
+    cell::{Cell, PhysicsType},
+    geometry_helpers::{VEC_DOWN, VEC_LEFT, VEC_RIGHT, VEC_UP},
+    world::PixelWorld,
+};
+
+// Islands with more cells than this are treated as anchored, which keeps the flood fill cheap next to large structures
+const MAX_ISLAND_CELLS: usize = 4096;
+
+// Solid cells cut out of the world because they were floating
+pub struct CellIsland {
+    // World position of the bottom left corner
+    pub position: IVec2,
+    pub size: UVec2,
+    // Cells in the bounds of the island, bottom row first, positions that are not part of the island are empty
+    pub cells: Vec<Cell>,
+}
+
+impl PixelWorld {
+    // Flood fills from the solid neighbors of every removed solid cell
+    // Islands which do not reach the bottom or sides of the world and do not rest on soft solids are removed from the world and returned
+    pub fn take_floating_islands(&mut self) -> Vec<CellIsland> {
+        let removed = std::mem::take(&mut self.removed_solids);
+
+        // Cells known to be connected to an anchor
+        let mut anchored: HashSet<IVec2> = HashSet::new();
+        let mut islands = Vec::new();
+        for position in removed {
+            for direction in [VEC_UP, VEC_DOWN, VEC_LEFT, VEC_RIGHT] {
+                let start = position + direction;
+                if anchored.contains(&start) || !self.is_hard_solid(start) {
+                    continue;
+                }
+                match self.fill_island(start, &anchored) {
+                    Ok(cells) => islands.push(self.cut_island(&cells)),
+                    Err(visited) => anchored.extend(visited),
+                }
+            }
+        }
+
+        // Cutting out the islands removed solid cells, those are already handled
+        self.removed_solids.clear();
+        islands
+    }
+
+    fn is_hard_solid(&self, position: IVec2) -> bool {
+        self.get_cell(position)
+            .is_some_and(|cell| cell.physics == PhysicsType::HardSolid)
+    }
+
+    // Cells on the bottom or sides of the loaded world hold up everything connected to them, as do cells resting on soft solids
+    // In streaming worlds this includes cells next to chunks which are not loaded, as they could rest on something there
+    // Fire burns out and rigid bodies move away, so neither holds anything up
+    fn is_anchor(&self, position: IVec2) -> bool {
+        [VEC_DOWN, VEC_LEFT, VEC_RIGHT]
+            .into_iter()
+            .any(|direction| {
+                self.get_cell(position + direction)
+                    .is_none_or(|cell| cell.physics == PhysicsType::SoftSolid)
+            })
+    }
+
+    // Finds all hard solid cells connected to the start cell
+    // Returns the visited cells as an error if they are connected to an anchor or there are too many of them
+    fn fill_island(
+        &self,
+        start: IVec2,
+        anchored: &HashSet<IVec2>,
+    ) -> Result<Vec<IVec2>, Vec<IVec2>> {
+        let mut visited = HashSet::new();
+        visited.insert(start);
+        let mut cells = vec![start];
+        let mut stack = vec![start];
+
+        while let Some(position) = stack.pop() {
+            if self.is_anchor(position) || cells.len() > MAX_ISLAND_CELLS {
+                return Err(cells);
+            }
+            for direction in [VEC_UP, VEC_DOWN, VEC_LEFT, VEC_RIGHT] {
+                let next = position + direction;
+                if anchored.contains(&next) {
+                    return Err(cells);
+                }
+                if !visited.contains(&next) && self.is_hard_solid(next) {
+                    visited.insert(next);
+                    cells.push(next);
+                    stack.push(next);
+                }
+            }
+        }
+        Ok(cells)
+    }
+
+    // Removes the cells from the world, copying them into an island
+    fn cut_island(&mut self, cells: &[IVec2]) -> CellIsland {
+        let min = cells.iter().copied().reduce(IVec2::min).unwrap();
+        let max = cells.iter().copied().reduce(IVec2::max).unwrap();
+        let size = (max - min + IVec2::ONE).as_uvec2();
+
+        let mut island = CellIsland {
+            position: min,
+            size,
+            cells: vec![Cell::default(); (size.x * size.y) as usize],
+        };
+        for &position in cells {
+            let local = position - min;
+            island.cells[(local.y * size.x as i32 + local.x) as usize] =
+                self.get_cell(position).unwrap();
+            self.set_cell(position, Cell::default());
+        }
+        island
+    }
+}
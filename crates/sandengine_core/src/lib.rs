@@ -0,0 +1,25 @@
+//! Core of the pixel simulation, usable without a window, renderer or physics engine
+//! `PixelWorld` holds the cells of the world split into chunks, which are simulated in parallel with `PixelWorld::step`
+//! Cells are placed with `PixelWorld::set_cell` or `PixelWorld::set_material` and read back with `PixelWorld::get_cell`
+//! New chunks are filled by a `ChunkGenerator`, such as the noise based `TerrainGenerator`
+//! Chunks can be added and removed with `PixelWorld::load_chunk` and `PixelWorld::unload_chunk`, so worlds can stream in around the player
+//! Chunks are kept in a `ChunkGrid` with stable indices, worlds that stream chunks make their grid sparse so chunks can be loaded at any position
+//! Chunks of one checkerboard phase are handed out as non overlapping neighborhoods, with `Execution::SingleThreaded` the phases run on the calling thread so `cargo miri test` can check them
+//! Edits made through an `EditHistory` can be undone and redone
+//! The game builds its rendering, input and rigid body plugins on top of this crate
+
+pub mod cell;
+pub mod chunk;
+pub mod chunk_grid;
+mod chunk_handler;
+pub mod explosion;
+pub mod generation;
+pub mod geometry_helpers;
+pub mod history;
+pub mod islands;
+pub mod material;
+pub mod neighborhood;
+mod pressure;
+pub mod random;
+pub mod save;
+pub mod world;
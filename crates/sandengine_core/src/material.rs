@@ -0,0 +1,529 @@
+//! Materials of the pixel simulation
+//! Materials are defined in a `.materials.ron` asset so that new materials can be added without recompiling
+//! The `MaterialRegistry` resource is built from the loaded definitions and is used to look up a cell's material by id
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::HashMap,
+};
+use serde::Deserialize;
+
+use super::cell::{Cell, PhysicsType, Temperature, AMBIENT_TEMPERATURE};
+
+// Compact identifier of a material, it is the index of the material inside of the registry
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MaterialId(pub u16);
+
+impl MaterialId {
+    // The empty material is always the first material in the registry
+    pub const EMPTY: MaterialId = MaterialId(0);
+
+    // Id of the same material in a reloaded registry, from the table of `MaterialRegistry::remap_from`
+    pub fn remap(self, remap: &[MaterialId]) -> MaterialId {
+        remap
+            .get(self.0 as usize)
+            .copied()
+            .unwrap_or(MaterialId::EMPTY)
+    }
+}
+
+// Flags a material can be given in the definitions file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum MaterialFlag {
+    // Do not show the material in the placement controls
+    Hidden,
+}
+
+// Set of material flags packed into a single byte
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MaterialFlags(u8);
+
+impl MaterialFlags {
+    pub fn contains(&self, flag: MaterialFlag) -> bool {
+        self.0 & Self::bit(flag) != 0
+    }
+
+    fn bit(flag: MaterialFlag) -> u8 {
+        1 << flag as u8
+    }
+}
+
+impl From<&[MaterialFlag]> for MaterialFlags {
+    fn from(flags: &[MaterialFlag]) -> Self {
+        Self(flags.iter().fold(0, |bits, flag| bits | Self::bit(*flag)))
+    }
+}
+
+// Temperature threshold of a phase transition
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum Threshold {
+    Above(f32),
+    Below(f32),
+}
+
+impl Threshold {
+    pub fn is_crossed(&self, temperature: Temperature) -> bool {
+        let temperature = temperature.celsius();
+        match *self {
+            Threshold::Above(threshold) => temperature > threshold,
+            Threshold::Below(threshold) => temperature < threshold,
+        }
+    }
+}
+
+// Change into another material when the temperature crosses the threshold, such as water boiling into steam
+#[derive(Clone, Debug, Deserialize)]
+pub struct PhaseTransitionDefinition {
+    pub when: Threshold,
+    // Name of the material to change into
+    pub into: String,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PhaseTransition {
+    pub when: Threshold,
+    pub into: MaterialId,
+}
+
+// Two materials touching each other turn into two new materials, such as water and lava becoming steam and stone
+// The probability is checked every time either of the two cells is updated
+#[derive(Clone, Debug, Deserialize)]
+pub struct ReactionDefinition {
+    pub a: String,
+    pub b: String,
+    pub a_into: String,
+    pub b_into: String,
+    pub probability: f32,
+}
+
+// A reaction from the point of view of one of its two materials
+#[derive(Clone, Copy, Debug)]
+pub struct Reaction {
+    // Material of the neighbor this material reacts with
+    pub with: MaterialId,
+    // Material this cell turns into
+    pub into: MaterialId,
+    // Material the neighbor turns into
+    pub other_into: MaterialId,
+    pub probability: f32,
+}
+
+// Blast set off when an explosive material catches fire
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct ExplosionDefinition {
+    pub radius: f32,
+    pub force: f32,
+}
+
+// A single material as it is written in the definitions file
+#[derive(Clone, Debug, Deserialize)]
+pub struct MaterialDefinition {
+    pub name: String,
+    // Base color of the material
+    pub color: [u8; 4],
+    // Maximum random offset applied to each color channel (except alpha) of a new cell
+    #[serde(default)]
+    pub color_noise: u8,
+    pub physics: PhysicsType,
+    #[serde(default)]
+    pub density: f32,
+    // How fast heat moves through the material, between 0 (insulator) and 1
+    #[serde(default)]
+    pub conductivity: f32,
+    // Temperature of newly placed cells
+    #[serde(default = "ambient_temperature")]
+    pub temperature: f32,
+    // Furthest a liquid or gas spreads sideways in one update
+    #[serde(default = "default_dispersion")]
+    pub dispersion: u16,
+    // Chance each update that a liquid holds still instead of spreading sideways, between 0 and 1
+    #[serde(default)]
+    pub viscosity: f32,
+    #[serde(default)]
+    pub transitions: Vec<PhaseTransitionDefinition>,
+    // Updates a new cell lasts before a fire burns out or a gas fades away, 0 if it lasts forever
+    #[serde(default)]
+    pub lifetime: u16,
+    // Chance each update to catch fire next to a burning cell, between 0 and 1
+    #[serde(default)]
+    pub flammability: f32,
+    // Updates the material burns for once it has caught fire
+    #[serde(default)]
+    pub burn_time: u16,
+    // Name of the fire material this material turns into when it catches fire
+    #[serde(default)]
+    pub ignites_into: Option<String>,
+    // Name of the material a burning cell gives off, such as smoke
+    #[serde(default)]
+    pub emits: Option<String>,
+    // Force of an explosion needed to break the material, cells that are not broken stay in place
+    #[serde(default)]
+    pub strength: f32,
+    // Explosion set off instead of burning when the material catches fire
+    #[serde(default)]
+    pub explosion: Option<ExplosionDefinition>,
+    #[serde(default)]
+    pub flags: Vec<MaterialFlag>,
+}
+
+fn ambient_temperature() -> f32 {
+    AMBIENT_TEMPERATURE.celsius()
+}
+
+fn default_dispersion() -> u16 {
+    2
+}
+
+// Asset holding all material definitions of a `.materials.ron` file
+#[derive(Asset, TypePath, Clone, Debug, Deserialize)]
+pub struct MaterialDefinitions {
+    pub materials: Vec<MaterialDefinition>,
+    #[serde(default)]
+    pub reactions: Vec<ReactionDefinition>,
+}
+
+impl MaterialDefinitions {
+    // Parses the contents of a `.materials.ron` file, for use without the asset server
+    pub fn from_ron(bytes: &[u8]) -> Result<Self, ron::error::SpannedError> {
+        ron::de::from_bytes(bytes)
+    }
+}
+
+// A material as it is used by the simulation
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: String,
+    pub color: [u8; 4],
+    pub color_noise: u8,
+    pub physics: PhysicsType,
+    pub density: f32,
+    pub conductivity: f32,
+    pub temperature: Temperature,
+    pub dispersion: u16,
+    pub viscosity: f32,
+    pub transitions: Vec<PhaseTransition>,
+    pub lifetime: u16,
+    pub flammability: f32,
+    pub burn_time: u16,
+    pub ignites_into: Option<MaterialId>,
+    pub emits: Option<MaterialId>,
+    pub strength: f32,
+    pub explosion: Option<ExplosionDefinition>,
+    pub reactions: Vec<Reaction>,
+    pub flags: MaterialFlags,
+}
+
+impl Material {
+    fn empty() -> Self {
+        Self {
+            name: "Empty".to_string(),
+            color: [0, 0, 0, 0],
+            color_noise: 0,
+            physics: PhysicsType::Empty,
+            density: 0.,
+            conductivity: 0.,
+            temperature: AMBIENT_TEMPERATURE,
+            dispersion: 0,
+            viscosity: 0.,
+            transitions: Vec::new(),
+            lifetime: 0,
+            flammability: 0.,
+            burn_time: 0,
+            ignites_into: None,
+            emits: None,
+            strength: 0.,
+            explosion: None,
+            reactions: Vec::new(),
+            flags: MaterialFlags::default(),
+        }
+    }
+
+    // Color of a cell with the given shade, the base color with a slight noise
+    // The noise of each shade is fixed, so a cell keeps its color for as long as it keeps its material
+    pub fn shade_color(&self, shade: u8) -> [u8; 4] {
+        if self.color_noise == 0 {
+            return self.color;
+        }
+        let noise = self.color_noise as u32;
+        let mut color = self.color;
+        for (i, channel) in color.iter_mut().take(3).enumerate() {
+            // Scrambles the shade and channel into an offset in -noise..noise
+            let hash = (shade as u32 * 3 + i as u32).wrapping_mul(0x9e3779b1) >> 16;
+            let offset = (hash % (noise * 2)) as i16 - noise as i16;
+            *channel = (*channel as i16 + offset).clamp(0, 255) as u8;
+        }
+        color
+    }
+
+    // Material this material changes into at the given temperature, if any
+    pub fn phase_transition(&self, temperature: Temperature) -> Option<MaterialId> {
+        self.transitions
+            .iter()
+            .find(|transition| transition.when.is_crossed(temperature))
+            .map(|transition| transition.into)
+    }
+}
+
+impl From<&MaterialDefinition> for Material {
+    fn from(definition: &MaterialDefinition) -> Self {
+        Self {
+            name: definition.name.clone(),
+            color: definition.color,
+            color_noise: definition.color_noise,
+            physics: definition.physics,
+            density: definition.density,
+            conductivity: definition.conductivity,
+            temperature: Temperature::from_celsius(definition.temperature),
+            dispersion: definition.dispersion,
+            viscosity: definition.viscosity,
+            // Transitions, fire and emitted materials refer to other materials by name, they are resolved once all materials are registered
+            transitions: Vec::new(),
+            lifetime: definition.lifetime,
+            flammability: definition.flammability,
+            burn_time: definition.burn_time,
+            ignites_into: None,
+            emits: None,
+            strength: definition.strength,
+            explosion: definition.explosion,
+            reactions: Vec::new(),
+            flags: MaterialFlags::from(definition.flags.as_slice()),
+        }
+    }
+}
+
+// Registry of all materials, cells only store the id of their material and look the rest up here
+// The empty material always exists as the first material, the definitions follow in the order they are written
+#[derive(Resource, Clone, Debug)]
+pub struct MaterialRegistry {
+    materials: Vec<Material>,
+    ids: HashMap<String, MaterialId>,
+    // Colors of the shades of every material, indexed by the material id
+    // A shade always indexes into the palette of its material, so looking up a color needs no bounds check
+    palettes: Vec<[[u8; 4]; 256]>,
+}
+
+impl Default for MaterialRegistry {
+    fn default() -> Self {
+        let empty = Material::empty();
+        let mut ids = HashMap::new();
+        ids.insert(empty.name.clone(), MaterialId::EMPTY);
+        let mut registry = Self {
+            materials: vec![empty],
+            ids,
+            palettes: Vec::new(),
+        };
+        registry.build_palettes();
+        registry
+    }
+}
+
+impl MaterialRegistry {
+    pub fn from_definitions(definitions: &MaterialDefinitions) -> Self {
+        let mut registry = Self::default();
+        for definition in definitions.materials.iter() {
+            let id = MaterialId(registry.materials.len() as u16);
+            registry.ids.insert(definition.name.clone(), id);
+            registry.materials.push(Material::from(definition));
+        }
+
+        for (i, definition) in definitions.materials.iter().enumerate() {
+            let transitions = definition
+                .transitions
+                .iter()
+                .filter_map(|transition| {
+                    registry
+                        .resolve(&definition.name, &transition.into)
+                        .map(|into| PhaseTransition {
+                            when: transition.when,
+                            into,
+                        })
+                })
+                .collect();
+            let ignites_into = definition
+                .ignites_into
+                .as_ref()
+                .and_then(|name| registry.resolve(&definition.name, name));
+            let emits = definition
+                .emits
+                .as_ref()
+                .and_then(|name| registry.resolve(&definition.name, name));
+
+            // Offset by one for the empty material
+            let material = &mut registry.materials[i + 1];
+            material.transitions = transitions;
+            material.ignites_into = ignites_into;
+            material.emits = emits;
+        }
+
+        // Reactions are stored on both materials so that they are found when either cell is updated
+        for reaction in definitions.reactions.iter() {
+            let context = format!("reaction of {} and {}", reaction.a, reaction.b);
+            let [Some(a), Some(b), Some(a_into), Some(b_into)] =
+                [&reaction.a, &reaction.b, &reaction.a_into, &reaction.b_into]
+                    .map(|name| registry.resolve(&context, name))
+            else {
+                continue;
+            };
+            registry.materials[a.0 as usize].reactions.push(Reaction {
+                with: b,
+                into: a_into,
+                other_into: b_into,
+                probability: reaction.probability,
+            });
+            if a != b {
+                registry.materials[b.0 as usize].reactions.push(Reaction {
+                    with: a,
+                    into: b_into,
+                    other_into: a_into,
+                    probability: reaction.probability,
+                });
+            }
+        }
+        registry.build_palettes();
+        registry
+    }
+
+    fn build_palettes(&mut self) {
+        self.palettes = self
+            .materials
+            .iter()
+            .map(|material| std::array::from_fn(|shade| material.shade_color(shade as u8)))
+            .collect();
+    }
+
+    // Color a cell is rendered with, looked up from its material and shade
+    #[inline]
+    pub fn cell_color(&self, cell: &Cell) -> [u8; 4] {
+        // Gases, rigid bodies and unknown materials share a single branch, as this runs for every cell of every chunk that is rendered
+        match self.palettes.get(cell.material.0 as usize) {
+            Some(palette) if !matches!(cell.physics, PhysicsType::Gas | PhysicsType::RigidBody) => {
+                palette[cell.shade as usize]
+            }
+            _ => self.special_cell_color(cell),
+        }
+    }
+
+    #[inline]
+    fn shade_color(&self, cell: &Cell) -> [u8; 4] {
+        self.palettes
+            .get(cell.material.0 as usize)
+            .map_or([0; 4], |palette| palette[cell.shade as usize])
+    }
+
+    // Kept out of line so that the loop over all cells stays small, few cells take this path
+    #[cold]
+    fn special_cell_color(&self, cell: &Cell) -> [u8; 4] {
+        let mut color = self.shade_color(cell);
+        match cell.physics {
+            // Rigid body cells without a material fill the world where bodies are, they are drawn black under the sprite of the body
+            PhysicsType::RigidBody if cell.material == MaterialId::EMPTY => color = [0, 0, 0, 255],
+            // Gases fade out as their lifetime runs out
+            PhysicsType::Gas if cell.lifetime > 0 => {
+                let lifetime = self.get(cell.material).lifetime as u32;
+                if lifetime > 0 {
+                    let remaining = (cell.lifetime as u32).min(lifetime);
+                    color[3] = (color[3] as u32 * remaining / lifetime) as u8;
+                }
+            }
+            _ => {}
+        }
+        color
+    }
+
+    // Looks up a material referenced by name somewhere in the definitions
+    fn resolve(&self, context: &str, name: &str) -> Option<MaterialId> {
+        let id = self.id(name);
+        if id.is_none() {
+            warn!("Unknown material {name} in {context}");
+        }
+        id
+    }
+
+    // True once materials other than the empty material have been registered
+    pub fn is_loaded(&self) -> bool {
+        self.materials.len() > 1
+    }
+
+    // Ids which are not in the registry give the empty material, such as ids of cells that were not remapped yet after a reload
+    pub fn get(&self, id: MaterialId) -> &Material {
+        self.materials
+            .get(id.0 as usize)
+            .unwrap_or(&self.materials[MaterialId::EMPTY.0 as usize])
+    }
+
+    // Table from the ids of an older registry to the ids of the same materials in this one, matched by name
+    // Materials which are no longer defined map to the empty material
+    pub fn remap_from(&self, old: &MaterialRegistry) -> Vec<MaterialId> {
+        old.materials
+            .iter()
+            .map(|material| self.id(&material.name).unwrap_or(MaterialId::EMPTY))
+            .collect()
+    }
+
+    // Find a material by the name it was given in the definitions
+    pub fn id(&self, name: &str) -> Option<MaterialId> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (MaterialId, &Material)> {
+        self.materials
+            .iter()
+            .enumerate()
+            .map(|(i, material)| (MaterialId(i as u16), material))
+    }
+}
+
+#[derive(Debug)]
+pub enum MaterialDefinitionsLoaderError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl std::fmt::Display for MaterialDefinitionsLoaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "could not read material definitions: {err}"),
+            Self::Ron(err) => write!(f, "could not parse material definitions: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for MaterialDefinitionsLoaderError {}
+
+impl From<std::io::Error> for MaterialDefinitionsLoaderError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<ron::error::SpannedError> for MaterialDefinitionsLoaderError {
+    fn from(err: ron::error::SpannedError) -> Self {
+        Self::Ron(err)
+    }
+}
+
+// Loads `.materials.ron` files as material definitions assets
+#[derive(Default)]
+pub struct MaterialDefinitionsLoader;
+
+impl AssetLoader for MaterialDefinitionsLoader {
+    type Asset = MaterialDefinitions;
+    type Settings = ();
+    type Error = MaterialDefinitionsLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(MaterialDefinitions::from_ron(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["materials.ron"]
+    }
+}
@@ -0,0 +1,210 @@
+//! Access to the 3x3 neighborhood of chunks around a chunk being simulated
+//! Chunks are simulated in four checkerboard phases, the centers of one phase are always two chunks apart from each other
+//! A neighborhood may only touch its center chunk and the half of each neighbor that is closest to the center,
+//! which means that the neighborhoods of a phase never overlap. Every access is checked against this, in release builds as well
+
+use std::marker::PhantomData;
+
+use bevy::math::{IVec2, UVec2};
+
+use super::{
+    cell::Cell,
+    chunk_grid::{ChunkGrid, ChunkIndices},
+    geometry_helpers::DIRECTIONS,
+};
+
+// How the chunks of each checkerboard phase are simulated
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Execution {
+    // Chunks of a phase are simulated in parallel on the compute task pool
+    #[default]
+    Parallel,
+    // Chunks are simulated one after another on the calling thread, this also runs under Miri
+    SingleThreaded,
+}
+
+// Cells of a chunk which are shared between the neighborhoods of a phase
+#[derive(Clone, Copy)]
+struct SharedChunk<'a> {
+    cells: *mut Cell,
+    len: usize,
+    _cells: PhantomData<&'a mut [Cell]>,
+}
+
+// Safety: every neighborhood only accesses its own part of a shared chunk, see `ChunkNeighborhood::in_reach`
+unsafe impl Send for SharedChunk<'_> {}
+unsafe impl Sync for SharedChunk<'_> {}
+
+// Chunks of the world while they are being simulated, by the index of the chunk in the grid
+pub(crate) struct SharedChunks<'a> {
+    chunks: Vec<Option<SharedChunk<'a>>>,
+    indices: &'a ChunkIndices,
+    chunk_size: UVec2,
+}
+
+impl<'a> SharedChunks<'a> {
+    pub fn new(grid: &'a mut ChunkGrid, chunk_size: UVec2) -> Self {
+        let (indices, chunks) = grid.split_mut();
+        let chunks = chunks
+            .iter_mut()
+            .map(|chunk| {
+                let cells = chunk.as_mut()?.cells.as_mut_slice();
+                Some(SharedChunk {
+                    cells: cells.as_mut_ptr(),
+                    len: cells.len(),
+                    _cells: PhantomData,
+                })
+            })
+            .collect();
+        Self {
+            chunks,
+            indices,
+            chunk_size,
+        }
+    }
+
+    fn get(&self, position: IVec2) -> Option<SharedChunk<'a>> {
+        self.chunks[self.indices.index(position)?]
+    }
+
+    // Neighborhoods around the centers which are in the given checkerboard phase, other centers are skipped
+    // Borrowing mutably makes sure the neighborhoods of one phase are gone before the next phase starts
+    pub fn phase(
+        &mut self,
+        phase: IVec2,
+        centers: &[IVec2],
+    ) -> Vec<(IVec2, ChunkNeighborhood<'_>)> {
+        centers
+            .iter()
+            .filter(|center| (**center + phase).rem_euclid(IVec2::splat(2)) == IVec2::ZERO)
+            .map(|center| {
+                let chunks = DIRECTIONS.map(|direction| self.get(*center + direction));
+                (
+                    *center,
+                    ChunkNeighborhood {
+                        chunks,
+                        chunk_size: self.chunk_size,
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+// The 3x3 chunks around a center chunk, chunks are None if there is no neighbor such as on world boundaries
+// Chunks are indexed like `DIRECTIONS`, the center chunk is the fourth one
+pub(crate) struct ChunkNeighborhood<'a> {
+    chunks: [Option<SharedChunk<'a>>; 9],
+    chunk_size: UVec2,
+}
+
+impl ChunkNeighborhood<'_> {
+    pub fn get(&self, chunk: usize, index: usize) -> Option<&Cell> {
+        let shared = self.shared(chunk, index)?;
+        // Safety: the index is inside of the chunk and no other neighborhood of the phase can reach it
+        Some(unsafe { &*shared.cells.add(index) })
+    }
+
+    pub fn get_mut(&mut self, chunk: usize, index: usize) -> Option<&mut Cell> {
+        let shared = self.shared(chunk, index)?;
+        // Safety: as in `get`, the mutable borrow of the neighborhood keeps this the only reference to the cell
+        Some(unsafe { &mut *shared.cells.add(index) })
+    }
+
+    fn shared(&self, chunk: usize, index: usize) -> Option<SharedChunk<'_>> {
+        let shared = self.chunks[chunk]?;
+        assert!(index < shared.len);
+        // Checked in release builds too, the neighborhoods of a phase rely on it to never share a cell
+        assert!(
+            self.in_reach(chunk, index),
+            "cell {index} of neighbor {chunk} is outside of the neighborhood"
+        );
+        Some(shared)
+    }
+
+    // Cells of the center chunk and cells of a neighbor less than half a chunk away from the center can be reached
+    // The centers of a phase are two chunks apart, so the halves of a neighbor reached from either side never overlap
+    fn in_reach(&self, chunk: usize, index: usize) -> bool {
+        // Most cells accessed are in the center chunk, which is checked first to keep the check cheap
+        if chunk == 4 {
+            return true;
+        }
+        let offset = IVec2::new(chunk as i32 % 3 - 1, chunk as i32 / 3 - 1);
+        let size = self.chunk_size.as_ivec2();
+        let local = IVec2::new(index as i32 % size.x, index as i32 / size.x);
+        let reach = size / 2;
+
+        let axis_in_reach = |offset: i32, local: i32, size: i32, reach: i32| match offset {
+            -1 => local >= size - reach,
+            1 => local < reach,
+            _ => true,
+        };
+        axis_in_reach(offset.x, local.x, size.x, reach.x)
+            && axis_in_reach(offset.y, local.y, size.y, reach.y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{IVec2, UVec2};
+
+    use crate::{
+        cell::Cell,
+        material::{MaterialDefinitions, MaterialRegistry},
+        world::PixelWorld,
+    };
+
+    use super::{Execution, SharedChunks};
+
+    fn registry() -> MaterialRegistry {
+        let definitions = MaterialDefinitions::from_ron(
+            br#"(materials: [
+                (name: "Sand", color: (230, 195, 92, 255), physics: SoftSolid, density: 1.6),
+                (name: "Water", color: (20, 125, 205, 150), physics: Liquid, density: 1.0),
+            ])"#,
+        )
+        .unwrap();
+        MaterialRegistry::from_definitions(&definitions)
+    }
+
+    fn count(world: &PixelWorld) -> usize {
+        (0..world.world_size.y as i32)
+            .flat_map(|y| (0..world.world_size.x as i32).map(move |x| IVec2::new(x, y)))
+            .filter(|position| !world.get_cell(*position).unwrap().is_empty())
+            .count()
+    }
+
+    // Small enough to run under Miri, with cells crossing every chunk border
+    #[test]
+    fn single_threaded_phases_keep_cells() {
+        let materials = registry();
+        let sand = materials.id("Sand").unwrap();
+        let water = materials.id("Water").unwrap();
+        let mut world = PixelWorld::new(UVec2::new(16, 16), UVec2::new(2, 2), 1);
+        world.execution = Execution::SingleThreaded;
+        for x in 2..14 {
+            world.set_material(IVec2::new(x, 12), sand, &materials);
+            world.set_material(IVec2::new(x, 6), water, &materials);
+        }
+
+        let placed = count(&world);
+        for _ in 0..24 {
+            world.step(&materials);
+        }
+        assert_eq!(count(&world), placed);
+        assert!(!world.get_cell(IVec2::new(8, 0)).unwrap().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "outside of the neighborhood")]
+    fn access_past_half_of_neighbor_is_caught() {
+        let mut world = PixelWorld::new(UVec2::new(16, 16), UVec2::new(2, 2), 1);
+        let chunk_size = world.chunk_size;
+        let mut shared = SharedChunks::new(&mut world.chunks, chunk_size);
+        let mut phase = shared.phase(IVec2::ZERO, &[IVec2::ZERO]);
+        let (_, neighborhood) = &mut phase[0];
+        // The right neighbor's cell in the middle of its row belongs to the neighborhood on its other side
+        let index = (chunk_size.x / 2) as usize;
+        *neighborhood.get_mut(5, index).unwrap() = Cell::default();
+    }
+}
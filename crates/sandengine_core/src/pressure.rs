@@ -0,0 +1,209 @@
+//! Pressure of liquid bodies, which lets liquid rise through narrow gaps until it is level with the rest of its body
+//! Cells only see their direct neighbors while simulating, so connected liquid is found with a flood fill instead
+//! The pass runs serially after the chunk updates, which keeps it clear of the parallel checkerboard
+
+use bevy::{math::IVec2, utils::HashSet};
+
+use super::{
+    cell::{Cell, PhysicsType},
+    geometry_helpers::{BoundRect, VEC_DOWN, VEC_LEFT, VEC_RIGHT, VEC_UP},
+    material::MaterialId,
+    world::PixelWorld,
+};
+
+// Liquid bodies are equalized once every this many updates
+const PRESSURE_INTERVAL: u32 = 4;
+// Bodies larger than this are skipped, keeping the flood fill cheap for oceans
+const MAX_BODY_CELLS: usize = 16384;
+// Most cells moved in a single body each pass, so levels even out over a few updates
+const MAX_TRANSFERS: usize = 16;
+
+// Connected liquid cells of a single material
+struct LiquidBody {
+    // Cells with nothing above them
+    surfaces: Vec<IVec2>,
+    // Empty cells next to the body which are resting on something, liquid can be pushed into these
+    openings: Vec<IVec2>,
+}
+
+impl PixelWorld {
+    // Moves the highest surface cells of each liquid body in the simulated areas into the lowest openings of that body
+    // The areas are collected over the updates between passes, so bodies which went to sleep in between are still found
+    pub(super) fn equalize_liquids(&mut self, rects: &[(IVec2, BoundRect)]) {
+        for (position, rect) in rects {
+            let collected = self
+                .pressure_rects
+                .entry(*position)
+                .or_insert_with(BoundRect::empty);
+            *collected = collected.union(rect);
+        }
+        if !self.iteration.is_multiple_of(PRESSURE_INTERVAL) {
+            return;
+        }
+
+        // Sorted so that bodies are equalized in the same order every run
+        let mut rects: Vec<_> = std::mem::take(&mut self.pressure_rects)
+            .into_iter()
+            .collect();
+        rects.sort_by_key(|(position, _)| (position.y, position.x));
+
+        let mut seeds = Vec::new();
+        for (position, rect) in rects {
+            if rect.is_empty() {
+                continue;
+            }
+            let origin = position * self.chunk_size.as_ivec2();
+            for y in rect.min.y..=rect.max.y {
+                for x in rect.min.x..=rect.max.x {
+                    seeds.push(origin + IVec2 { x, y });
+                }
+            }
+        }
+
+        let mut visited = HashSet::new();
+        for seed in seeds {
+            if visited.contains(&seed) {
+                continue;
+            }
+            let Some(material) = self.resting_liquid(seed) else {
+                continue;
+            };
+            if let Some(body) = self.fill_liquid_body(seed, material, &mut visited) {
+                self.equalize_body(body);
+            }
+        }
+    }
+
+    // Material of a liquid cell which is not falling
+    // Falling liquid is not part of a body, otherwise streams would be pulled down to the bottom instantly
+    fn resting_liquid(&self, position: IVec2) -> Option<MaterialId> {
+        self.get_cell(position)
+            .filter(|cell| cell.physics == PhysicsType::Liquid && cell.velocity.y == 0)
+            .map(|cell| cell.material)
+    }
+
+    // Finds the surfaces and openings of the body connected to the start cell, None if the body is too large
+    fn fill_liquid_body(
+        &self,
+        start: IVec2,
+        material: MaterialId,
+        visited: &mut HashSet<IVec2>,
+    ) -> Option<LiquidBody> {
+        let mut body = LiquidBody {
+            surfaces: Vec::new(),
+            openings: Vec::new(),
+        };
+        let mut openings = HashSet::new();
+        let mut stack = vec![start];
+        visited.insert(start);
+        let mut size = 0;
+
+        while let Some(position) = stack.pop() {
+            size += 1;
+            if size > MAX_BODY_CELLS {
+                return None;
+            }
+            for direction in [VEC_UP, VEC_DOWN, VEC_LEFT, VEC_RIGHT] {
+                let next = position + direction;
+                if self.resting_liquid(next) == Some(material) {
+                    if visited.insert(next) {
+                        stack.push(next);
+                    }
+                } else if self.get_cell(next).is_some_and(|cell| cell.is_empty()) {
+                    if direction == VEC_UP {
+                        body.surfaces.push(position);
+                    }
+                    let supported = direction == VEC_UP
+                        || self
+                            .get_cell(next + VEC_DOWN)
+                            .is_some_and(|cell| !cell.is_empty());
+                    if supported && openings.insert(next) {
+                        body.openings.push(next);
+                    }
+                }
+            }
+        }
+        Some(body)
+    }
+
+    // Moving cells only when they are more than one cell higher than the opening keeps levels from flickering
+    fn equalize_body(&mut self, mut body: LiquidBody) {
+        body.surfaces.sort_by_key(|position| -position.y);
+        body.openings.sort_by_key(|position| position.y);
+
+        for (surface, opening) in body
+            .surfaces
+            .into_iter()
+            .zip(body.openings)
+            .take(MAX_TRANSFERS)
+        {
+            if surface.y <= opening.y + 1 {
+                break;
+            }
+            let Some(cell) = self.get_cell(surface) else {
+                continue;
+            };
+            self.set_cell(opening, cell);
+            self.set_cell(surface, Cell::default());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{IVec2, UVec2};
+
+    use crate::{
+        material::{MaterialDefinitions, MaterialRegistry},
+        neighborhood::Execution,
+        world::PixelWorld,
+    };
+
+    #[test]
+    fn connected_columns_level_out() {
+        let definitions = MaterialDefinitions::from_ron(
+            br#"(materials: [
+                (name: "Water", color: (20, 125, 205, 150), physics: Liquid, density: 1.0),
+                (name: "Stone", color: (120, 120, 120, 255), physics: HardSolid),
+            ])"#,
+        )
+        .unwrap();
+        let materials = MaterialRegistry::from_definitions(&definitions);
+        let water = materials.id("Water").unwrap();
+        let stone = materials.id("Stone").unwrap();
+        let mut world = PixelWorld::new(UVec2::new(32, 32), UVec2::new(2, 2), 1);
+        world.execution = Execution::SingleThreaded;
+
+        // Two tubes joined by a channel along the bottom, only the left one is filled
+        for y in 0..24 {
+            for x in 0..32 {
+                let channel = y < 2 && (4..22).contains(&x);
+                let tube = (4..6).contains(&x) || (20..22).contains(&x);
+                if channel || tube {
+                    if channel || x < 6 && y < 20 {
+                        world.set_material(IVec2::new(x, y), water, &materials);
+                    }
+                } else {
+                    world.set_material(IVec2::new(x, y), stone, &materials);
+                }
+            }
+        }
+
+        for _ in 0..400 {
+            world.step(&materials);
+        }
+        // Height of the water in a tube
+        let level = |x: i32| {
+            (0..24)
+                .take_while(|y| !world.get_cell(IVec2::new(x, *y)).unwrap().is_empty())
+                .count() as i32
+        };
+        let (left, right) = (level(4), level(20));
+        assert!(right > 2);
+        // Levels within two cells of each other are left alone, which keeps them from flickering
+        assert!(
+            (left - right).abs() <= 2,
+            "{left} and {right} are not level"
+        );
+    }
+}
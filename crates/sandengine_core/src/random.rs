@@ -0,0 +1,25 @@
+//! Seeded random numbers for the simulation, so that runs with the same seed and inputs give the same results
+//! Each chunk gets its own generator every update, derived from the world seed, the update and the position of the chunk
+//! The results then do not depend on the order in which the chunk tasks are scheduled
+
+use bevy::math::IVec2;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+pub type SimulationRng = ChaCha8Rng;
+
+// Generator for a chunk simulated in the given update
+pub(super) fn chunk_rng(seed: u64, iteration: u32, position: IVec2) -> SimulationRng {
+    let mut rng = SimulationRng::seed_from_u64(splitmix64(seed ^ splitmix64(iteration as u64)));
+    // Every chunk uses its own stream of the update's generator
+    rng.set_stream(((position.x as u32 as u64) << 32) | position.y as u32 as u64);
+    rng
+}
+
+// Scrambles the bits of a value, so that consecutive updates get unrelated seeds
+pub(super) fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
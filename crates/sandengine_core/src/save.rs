@@ -0,0 +1,755 @@
+//! Compact binary save files for pixel worlds
+//! A file starts with the magic number and version of its format, followed by a zlib compressed body
+//! Other kinds of files, such as recordings, reuse the body with a format of their own so they are not mistaken for saves
+//! The body starts with the names of all materials, cells store the ids of their materials, which are mapped back to the current materials by name when loading
+//! Games write their own data, such as the entities of the world, into the same body before or after the world
+
+use std::{
+    collections::HashSet,
+    fmt,
+    io::{Read, Write},
+};
+
+use bevy::math::{IVec2, UVec2, Vec2};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use rand::SeedableRng;
+
+use super::{
+    cell::{Cell, PhysicsType, ENCODED_CELL_SIZE},
+    chunk::PixelChunk,
+    generation::ChunkGenerator,
+    material::{MaterialId, MaterialRegistry},
+    random::SimulationRng,
+    world::{PixelWorld, MIN_CHUNK_SIZE},
+};
+
+// Header of a kind of file, files with another magic number or version are rejected
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileFormat {
+    pub magic: [u8; 4],
+    pub version: u16,
+}
+
+// Format of world saves
+pub const SAVE_FORMAT: FileFormat = FileFormat {
+    magic: *b"SAND",
+    version: 1,
+};
+
+// Chunks larger than this are assumed to come from a corrupt file
+const MAX_CHUNK_SIZE: u32 = 4096;
+// Chunk grids with more chunks than this are assumed to come from a corrupt file
+const MAX_CHUNK_AMOUNT: u32 = 1 << 16;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    // The file does not start with the magic number of the format
+    WrongFormat,
+    // The file was written by another version of the format
+    UnsupportedVersion { found: u16, expected: u16 },
+    // The file ended early or contains values that do not make sense
+    Corrupt,
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(err) => write!(f, "{err}"),
+            SaveError::WrongFormat => write!(f, "not a file of this kind"),
+            SaveError::UnsupportedVersion { found, expected } => {
+                write!(
+                    f,
+                    "file version {found} is not the supported version {expected}"
+                )
+            }
+            SaveError::Corrupt => write!(f, "file is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<std::io::Error> for SaveError {
+    fn from(err: std::io::Error) -> Self {
+        SaveError::Io(err)
+    }
+}
+
+// Writes the body of a save file, values are written in little endian
+pub struct SaveWriter {
+    format: FileFormat,
+    body: Vec<u8>,
+}
+
+impl SaveWriter {
+    pub fn new(materials: &MaterialRegistry) -> Self {
+        Self::with_format(SAVE_FORMAT, materials)
+    }
+
+    pub fn with_format(format: FileFormat, materials: &MaterialRegistry) -> Self {
+        let mut writer = Self {
+            format,
+            body: Vec::new(),
+        };
+        let names: Vec<&str> = materials
+            .iter()
+            .map(|(_, material)| material.name.as_str())
+            .collect();
+        writer.write_u16(names.len() as u16);
+        for name in names {
+            writer.write_str(name);
+        }
+        writer
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.body.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.body.extend(value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.body.extend(value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.body.extend(value.to_le_bytes());
+    }
+
+    pub fn write_i32(&mut self, value: i32) {
+        self.body.extend(value.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.body.extend(value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.body.extend(bytes);
+    }
+
+    pub fn write_str(&mut self, value: &str) {
+        self.write_u16(value.len() as u16);
+        self.write_bytes(value.as_bytes());
+    }
+
+    pub fn write_ivec2(&mut self, value: IVec2) {
+        self.write_i32(value.x);
+        self.write_i32(value.y);
+    }
+
+    pub fn write_uvec2(&mut self, value: UVec2) {
+        self.write_u32(value.x);
+        self.write_u32(value.y);
+    }
+
+    pub fn write_vec2(&mut self, value: Vec2) {
+        self.write_f32(value.x);
+        self.write_f32(value.y);
+    }
+
+    pub fn write_material(&mut self, material: MaterialId) {
+        self.write_u16(material.0);
+    }
+
+    // Writes the cells without their amount, which has to be known when reading them
+    pub fn write_cells(&mut self, cells: &[Cell]) {
+        for cell in cells {
+            cell.encode(&mut self.body);
+        }
+    }
+
+    // Compresses the body and puts the header in front of it
+    pub fn finish(self) -> Result<Vec<u8>, SaveError> {
+        let mut file = Vec::from(self.format.magic);
+        file.extend(self.format.version.to_le_bytes());
+        let mut encoder = ZlibEncoder::new(file, Compression::default());
+        encoder.write_all(&self.body)?;
+        Ok(encoder.finish()?)
+    }
+}
+
+// Reads the body of a save file, in the same order it was written
+pub struct SaveReader {
+    body: Vec<u8>,
+    offset: usize,
+    // Current material of each material id in the file, None if the material no longer exists
+    materials: Vec<Option<MaterialId>>,
+}
+
+impl SaveReader {
+    pub fn new(file: &[u8], materials: &MaterialRegistry) -> Result<Self, SaveError> {
+        Self::with_format(file, SAVE_FORMAT, materials)
+    }
+
+    pub fn with_format(
+        file: &[u8],
+        format: FileFormat,
+        materials: &MaterialRegistry,
+    ) -> Result<Self, SaveError> {
+        if file.get(..4) != Some(&format.magic) {
+            return Err(SaveError::WrongFormat);
+        }
+        let version = u16::from_le_bytes([
+            *file.get(4).ok_or(SaveError::Corrupt)?,
+            *file.get(5).ok_or(SaveError::Corrupt)?,
+        ]);
+        if version != format.version {
+            return Err(SaveError::UnsupportedVersion {
+                found: version,
+                expected: format.version,
+            });
+        }
+
+        let mut body = Vec::new();
+        ZlibDecoder::new(&file[6..])
+            .read_to_end(&mut body)
+            .map_err(|_| SaveError::Corrupt)?;
+
+        let mut reader = Self {
+            body,
+            offset: 0,
+            materials: Vec::new(),
+        };
+        let count = reader.read_u16()?;
+        for _ in 0..count {
+            let name = reader.read_string()?;
+            reader.materials.push(materials.id(&name));
+        }
+        Ok(reader)
+    }
+
+    pub fn read_bytes<const N: usize>(&mut self) -> Result<[u8; N], SaveError> {
+        let bytes = self
+            .body
+            .get(self.offset..self.offset + N)
+            .ok_or(SaveError::Corrupt)?;
+        self.offset += N;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, SaveError> {
+        Ok(self.read_bytes::<1>()?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, SaveError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, SaveError> {
+        Ok(u16::from_le_bytes(self.read_bytes()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, SaveError> {
+        Ok(u32::from_le_bytes(self.read_bytes()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, SaveError> {
+        Ok(u64::from_le_bytes(self.read_bytes()?))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, SaveError> {
+        Ok(i32::from_le_bytes(self.read_bytes()?))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, SaveError> {
+        Ok(f32::from_le_bytes(self.read_bytes()?))
+    }
+
+    pub fn read_string(&mut self) -> Result<String, SaveError> {
+        let length = self.read_u16()? as usize;
+        let bytes = self
+            .body
+            .get(self.offset..self.offset + length)
+            .ok_or(SaveError::Corrupt)?;
+        self.offset += length;
+        String::from_utf8(bytes.to_vec()).map_err(|_| SaveError::Corrupt)
+    }
+
+    pub fn read_ivec2(&mut self) -> Result<IVec2, SaveError> {
+        Ok(IVec2::new(self.read_i32()?, self.read_i32()?))
+    }
+
+    pub fn read_uvec2(&mut self) -> Result<UVec2, SaveError> {
+        Ok(UVec2::new(self.read_u32()?, self.read_u32()?))
+    }
+
+    pub fn read_vec2(&mut self) -> Result<Vec2, SaveError> {
+        Ok(Vec2::new(self.read_f32()?, self.read_f32()?))
+    }
+
+    // Materials which no longer exist are read as empty
+    pub fn read_material(&mut self) -> Result<MaterialId, SaveError> {
+        let id = self.read_u16()?;
+        Ok(self
+            .current_material(MaterialId(id))
+            .unwrap_or(MaterialId::EMPTY))
+    }
+
+    // Cells of materials which no longer exist are read as empty cells
+    pub fn read_cells(&mut self, count: usize) -> Result<Vec<Cell>, SaveError> {
+        let length = count
+            .checked_mul(ENCODED_CELL_SIZE)
+            .ok_or(SaveError::Corrupt)?;
+        let bytes = self
+            .body
+            .get(self.offset..self.offset.saturating_add(length))
+            .ok_or(SaveError::Corrupt)?;
+        self.offset += length;
+
+        let mut cells = Vec::with_capacity(count);
+        for bytes in bytes.chunks_exact(ENCODED_CELL_SIZE) {
+            let mut cell = Cell::decode(bytes).ok_or(SaveError::Corrupt)?;
+            match self.current_material(cell.material) {
+                Some(material) => cell.material = material,
+                // Rigid body cells do not need a material
+                None if cell.physics == PhysicsType::RigidBody => cell.material = MaterialId::EMPTY,
+                None => cell = Cell::default(),
+            }
+            cells.push(cell);
+        }
+        Ok(cells)
+    }
+
+    fn current_material(&self, material: MaterialId) -> Option<MaterialId> {
+        self.materials.get(material.0 as usize).copied().flatten()
+    }
+}
+
+impl PixelWorld {
+    // Writes the size, chunk layout, seed and all chunks of the world, including unloaded chunks
+    pub fn save(&self, writer: &mut SaveWriter) {
+        writer.write_u64(self.seed);
+        writer.write_u32(self.iteration);
+        // State of the generator, so a loaded world continues the same way the saved one would have
+        writer.write_bytes(&self.rng.get_seed());
+        writer.write_u64(self.rng.get_stream());
+        writer.write_bytes(&self.rng.get_word_pos().to_le_bytes());
+
+        writer.write_uvec2(self.world_size);
+        writer.write_uvec2(self.chunk_amount);
+        writer.write_uvec2(self.chunk_size);
+        writer.write_bool(self.chunks.is_sparse());
+
+        // Sorted, so the same world is always written the same way
+        let mut loaded: Vec<&PixelChunk> = self.chunks.iter().collect();
+        loaded.sort_by_key(|chunk| (chunk.position.y, chunk.position.x));
+        writer.write_u32(loaded.len() as u32);
+        for chunk in loaded {
+            writer.write_ivec2(chunk.position);
+            writer.write_cells(&chunk.cells);
+        }
+
+        let mut stored: Vec<(&IVec2, &Vec<u8>)> = self.stored_chunks.iter().collect();
+        stored.sort_by_key(|(position, _)| (position.y, position.x));
+        writer.write_u32(stored.len() as u32);
+        for (position, bytes) in stored {
+            writer.write_ivec2(*position);
+            writer.write_bytes(bytes);
+        }
+    }
+
+    // Reads a world written by `save`, the generator fills the chunks which were never created in the saved world
+    // Chunks which do not cover the world, and chunks which are stored twice mean the file is corrupt
+    // A world which was not streamed has to contain each chunk of its chunk amount exactly once
+    pub fn load(
+        reader: &mut SaveReader,
+        generator: impl ChunkGenerator + 'static,
+    ) -> Result<Self, SaveError> {
+        let seed = reader.read_u64()?;
+        let iteration = reader.read_u32()?;
+        let mut rng = SimulationRng::from_seed(reader.read_bytes()?);
+        rng.set_stream(reader.read_u64()?);
+        rng.set_word_pos(u128::from_le_bytes(reader.read_bytes()?));
+
+        let world_size = reader.read_uvec2()?;
+        let chunk_amount = reader.read_uvec2()?;
+        let chunk_size = reader.read_uvec2()?;
+        if chunk_size.min_element() < MIN_CHUNK_SIZE || chunk_size.max_element() > MAX_CHUNK_SIZE {
+            return Err(SaveError::Corrupt);
+        }
+        if chunk_amount.x as u64 * chunk_amount.y as u64 > MAX_CHUNK_AMOUNT as u64 {
+            return Err(SaveError::Corrupt);
+        }
+        if (chunk_amount.as_u64vec2() * chunk_size.as_u64vec2())
+            .cmplt(world_size.as_u64vec2())
+            .any()
+        {
+            return Err(SaveError::Corrupt);
+        }
+        let streamed = reader.read_bool()?;
+        let cell_count = (chunk_size.x * chunk_size.y) as usize;
+
+        let mut world = PixelWorld::without_chunks(
+            world_size,
+            chunk_amount,
+            chunk_size,
+            seed,
+            Box::new(generator),
+        );
+        world.iteration = iteration;
+        world.rng = rng;
+
+        let mut positions = HashSet::new();
+        let mut loaded = Vec::new();
+        for _ in 0..reader.read_u32()? {
+            let position = reader.read_ivec2()?;
+            if !positions.insert(position) {
+                return Err(SaveError::Corrupt);
+            }
+            let mut chunk = PixelChunk::new(chunk_size, position);
+            chunk.cells = reader.read_cells(cell_count)?;
+            loaded.push(chunk);
+        }
+
+        for _ in 0..reader.read_u32()? {
+            let position = reader.read_ivec2()?;
+            if !positions.insert(position) {
+                return Err(SaveError::Corrupt);
+            }
+            let mut bytes = Vec::with_capacity(cell_count * ENCODED_CELL_SIZE);
+            for cell in reader.read_cells(cell_count)? {
+                cell.encode(&mut bytes);
+            }
+            world.stored_chunks.insert(position, bytes);
+        }
+
+        // Only streamed worlds have chunks outside of the chunk amount, they need a sparse grid to hold them
+        if !streamed {
+            let dense = world.stored_chunks.is_empty()
+                && loaded.len() as u64 == chunk_amount.x as u64 * chunk_amount.y as u64
+                && loaded
+                    .iter()
+                    .all(|chunk| world.chunks.can_hold(chunk.position));
+            if !dense {
+                return Err(SaveError::Corrupt);
+            }
+        } else {
+            world.chunks.make_sparse();
+        }
+        for chunk in loaded {
+            world.chunks.insert(chunk);
+        }
+
+        Ok(world)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{IVec2, UVec2};
+    use rand::SeedableRng;
+
+    use crate::{
+        cell::Cell,
+        explosion::Explosion,
+        generation::EmptyGenerator,
+        history::EditHistory,
+        material::{MaterialDefinitions, MaterialId, MaterialRegistry},
+        random::SimulationRng,
+        world::PixelWorld,
+    };
+
+    use super::{FileFormat, SaveError, SaveReader, SaveWriter, MAX_CHUNK_SIZE};
+
+    fn registry() -> MaterialRegistry {
+        let definitions = MaterialDefinitions::from_ron(
+            br#"(materials: [
+                (name: "Sand", color: (230, 195, 92, 255), physics: SoftSolid, density: 1.6),
+                (name: "Water", color: (20, 125, 205, 150), physics: Liquid, density: 1.0),
+            ])"#,
+        )
+        .unwrap();
+        MaterialRegistry::from_definitions(&definitions)
+    }
+
+    fn save_and_load(world: &PixelWorld) -> Result<PixelWorld, SaveError> {
+        let materials = MaterialRegistry::default();
+        let mut writer = SaveWriter::new(&materials);
+        world.save(&mut writer);
+        let bytes = writer.finish()?;
+        PixelWorld::load(&mut SaveReader::new(&bytes, &materials)?, EmptyGenerator)
+    }
+
+    // Loads a world which is written by hand in the layout of `PixelWorld::save`, with the cells of each chunk left empty
+    fn load_layout(
+        chunk_amount: UVec2,
+        chunk_size: UVec2,
+        chunks: &[IVec2],
+        cells_per_chunk: usize,
+    ) -> Result<PixelWorld, SaveError> {
+        let materials = MaterialRegistry::default();
+        let mut writer = SaveWriter::new(&materials);
+        let rng = SimulationRng::seed_from_u64(0);
+        writer.write_u64(0);
+        writer.write_u32(0);
+        writer.write_bytes(&rng.get_seed());
+        writer.write_u64(rng.get_stream());
+        writer.write_bytes(&rng.get_word_pos().to_le_bytes());
+        writer.write_uvec2(chunk_amount * chunk_size);
+        writer.write_uvec2(chunk_amount);
+        writer.write_uvec2(chunk_size);
+        writer.write_bool(false);
+        writer.write_u32(chunks.len() as u32);
+        for position in chunks {
+            writer.write_ivec2(*position);
+            writer.write_cells(&vec![Cell::default(); cells_per_chunk]);
+        }
+        writer.write_u32(0);
+        let bytes = writer.finish()?;
+        PixelWorld::load(&mut SaveReader::new(&bytes, &materials)?, EmptyGenerator)
+    }
+
+    fn all_chunks() -> Vec<IVec2> {
+        vec![
+            IVec2::new(0, 0),
+            IVec2::new(1, 0),
+            IVec2::new(0, 1),
+            IVec2::new(1, 1),
+        ]
+    }
+
+    #[test]
+    fn loaded_world_matches_the_saved_one() {
+        let materials = registry();
+        let sand = materials.id("Sand").unwrap();
+        let water = materials.id("Water").unwrap();
+        let mut world = PixelWorld::new(UVec2::new(64, 64), UVec2::new(2, 2), 7);
+        let mut rng = SimulationRng::seed_from_u64(0);
+        for x in 10..50 {
+            world.set_cell(IVec2::new(x, 40), Cell::new(sand, &materials, &mut rng));
+            world.set_cell(IVec2::new(x, 50), Cell::new(water, &materials, &mut rng));
+        }
+        for _ in 0..10 {
+            world.step(&materials);
+        }
+
+        let mut writer = SaveWriter::new(&materials);
+        world.save(&mut writer);
+        let bytes = writer.finish().unwrap();
+        let mut loaded = PixelWorld::load(
+            &mut SaveReader::new(&bytes, &materials).unwrap(),
+            EmptyGenerator,
+        )
+        .unwrap();
+        assert_eq!(loaded.checksum(), world.checksum());
+
+        // The generator state is saved as well, so both worlds keep going the same way
+        for _ in 0..10 {
+            world.step(&materials);
+            loaded.step(&materials);
+        }
+        assert_eq!(loaded.checksum(), world.checksum());
+    }
+
+    #[test]
+    fn headers_which_do_not_match_the_chunks_are_rejected() {
+        let mut world = PixelWorld::new(UVec2::new(16, 16), UVec2::new(2, 2), 1);
+        assert!(save_and_load(&world).is_ok());
+
+        // The chunks would not cover the whole world
+        world.world_size = UVec2::new(24, 16);
+        assert!(matches!(save_and_load(&world), Err(SaveError::Corrupt)));
+
+        // Chunks outside of the chunk amount of a world which was not streamed
+        world.world_size = UVec2::new(8, 16);
+        world.chunk_amount = UVec2::new(1, 2);
+        assert!(matches!(save_and_load(&world), Err(SaveError::Corrupt)));
+    }
+
+    #[test]
+    fn layout_written_by_hand_loads() {
+        let chunk_size = UVec2::splat(8);
+        assert!(load_layout(UVec2::splat(2), chunk_size, &all_chunks(), 64).is_ok());
+    }
+
+    #[test]
+    fn chunks_larger_than_the_maximum_are_rejected() {
+        let chunk_size = UVec2::new(MAX_CHUNK_SIZE + 1, 8);
+        assert!(matches!(
+            load_layout(UVec2::ONE, chunk_size, &[], 0),
+            Err(SaveError::Corrupt)
+        ));
+    }
+
+    #[test]
+    fn chunks_outside_of_the_chunk_amount_are_rejected() {
+        let mut chunks = all_chunks();
+        chunks[3] = IVec2::new(2, 1);
+        assert!(matches!(
+            load_layout(UVec2::splat(2), UVec2::splat(8), &chunks, 64),
+            Err(SaveError::Corrupt)
+        ));
+    }
+
+    #[test]
+    fn duplicate_chunks_are_rejected() {
+        let mut chunks = all_chunks();
+        chunks.push(IVec2::new(1, 1));
+        assert!(matches!(
+            load_layout(UVec2::splat(2), UVec2::splat(8), &chunks, 64),
+            Err(SaveError::Corrupt)
+        ));
+    }
+
+    #[test]
+    fn missing_chunks_of_a_world_which_was_not_streamed_are_rejected() {
+        let chunks = &all_chunks()[..3];
+        assert!(matches!(
+            load_layout(UVec2::splat(2), UVec2::splat(8), chunks, 64),
+            Err(SaveError::Corrupt)
+        ));
+    }
+
+    #[test]
+    fn truncated_cells_are_rejected() {
+        assert!(matches!(
+            load_layout(UVec2::splat(2), UVec2::splat(8), &all_chunks(), 63),
+            Err(SaveError::Corrupt)
+        ));
+    }
+
+    // Inputs of a recording, each applied before the step it is stamped with
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Input {
+        Brush(IVec2, MaterialId),
+        FinishStroke,
+        Undo,
+        Explode(IVec2),
+    }
+
+    const RECORDING_FORMAT: FileFormat = FileFormat {
+        magic: *b"TREC",
+        version: 1,
+    };
+
+    // Plays the inputs into a new world of the same seed, returning its checksum once all steps are done
+    fn play(inputs: &[(u32, Input)], steps: u32, materials: &MaterialRegistry) -> u64 {
+        let mut world = PixelWorld::new(UVec2::new(64, 64), UVec2::new(2, 2), 11);
+        let mut history = EditHistory::default();
+        let mut inputs = inputs.iter().peekable();
+        for step in 0..steps {
+            while let Some((_, input)) = inputs.next_if(|(at, _)| *at == step) {
+                match *input {
+                    Input::Brush(center, material) => {
+                        for y in -2..=2 {
+                            for x in -2..=2 {
+                                let position = center + IVec2::new(x, y);
+                                history.set_material(&mut world, position, material, materials);
+                            }
+                        }
+                    }
+                    Input::FinishStroke => history.commit(),
+                    Input::Undo => {
+                        history.undo(&mut world);
+                    }
+                    Input::Explode(center) => {
+                        let explosion = Explosion {
+                            center,
+                            radius: 4.,
+                            force: 2.,
+                            undoable: true,
+                        };
+                        history.explode(&mut world, &explosion, materials);
+                    }
+                }
+            }
+            world.step(materials);
+        }
+        world.checksum()
+    }
+
+    #[test]
+    fn recorded_inputs_replay_into_the_same_world() {
+        let materials = registry();
+        let sand = materials.id("Sand").unwrap();
+        let water = materials.id("Water").unwrap();
+        let inputs = vec![
+            (0, Input::Brush(IVec2::new(20, 50), sand)),
+            (1, Input::Brush(IVec2::new(22, 50), sand)),
+            (2, Input::FinishStroke),
+            (4, Input::Brush(IVec2::new(40, 40), water)),
+            (4, Input::FinishStroke),
+            (6, Input::Undo),
+            (8, Input::Brush(IVec2::new(30, 60), water)),
+            (9, Input::FinishStroke),
+            (15, Input::Explode(IVec2::new(21, 10))),
+        ];
+        let steps = 30;
+
+        let mut writer = SaveWriter::with_format(RECORDING_FORMAT, &materials);
+        writer.write_u32(steps);
+        writer.write_u32(inputs.len() as u32);
+        for (step, input) in &inputs {
+            writer.write_u32(*step);
+            match *input {
+                Input::Brush(position, material) => {
+                    writer.write_u8(0);
+                    writer.write_ivec2(position);
+                    writer.write_material(material);
+                }
+                Input::FinishStroke => writer.write_u8(1),
+                Input::Undo => writer.write_u8(2),
+                Input::Explode(position) => {
+                    writer.write_u8(3);
+                    writer.write_ivec2(position);
+                }
+            }
+        }
+        let bytes = writer.finish().unwrap();
+
+        // A recording is not a save, even though its body is written the same way
+        assert!(matches!(
+            SaveReader::new(&bytes, &materials),
+            Err(SaveError::WrongFormat)
+        ));
+
+        let mut reader = SaveReader::with_format(&bytes, RECORDING_FORMAT, &materials).unwrap();
+        let replay_steps = reader.read_u32().unwrap();
+        let mut replay = Vec::new();
+        for _ in 0..reader.read_u32().unwrap() {
+            let step = reader.read_u32().unwrap();
+            let input = match reader.read_u8().unwrap() {
+                0 => Input::Brush(
+                    reader.read_ivec2().unwrap(),
+                    reader.read_material().unwrap(),
+                ),
+                1 => Input::FinishStroke,
+                2 => Input::Undo,
+                3 => Input::Explode(reader.read_ivec2().unwrap()),
+                kind => panic!("unknown input {kind}"),
+            };
+            replay.push((step, input));
+        }
+        assert_eq!(replay, inputs);
+
+        let recorded = play(&inputs, steps, &materials);
+        assert_eq!(play(&replay, replay_steps, &materials), recorded);
+        // The inputs do change the world, an empty world would match as well
+        assert_ne!(play(&[], steps, &materials), recorded);
+    }
+
+    #[test]
+    fn other_versions_of_a_format_are_rejected() {
+        let materials = MaterialRegistry::default();
+        let newer = FileFormat {
+            version: RECORDING_FORMAT.version + 1,
+            ..RECORDING_FORMAT
+        };
+        let bytes = SaveWriter::with_format(newer, &materials).finish().unwrap();
+        assert!(matches!(
+            SaveReader::with_format(&bytes, RECORDING_FORMAT, &materials),
+            Err(SaveError::UnsupportedVersion {
+                found: 2,
+                expected: 1
+            })
+        ));
+    }
+}
@@ -0,0 +1,908 @@
+use bevy::{
+    math::{IVec2, UVec2},
+    prelude::Component,
+    tasks::{ComputeTaskPool, TaskPool},
+    utils::hashbrown::HashMap,
+};
+
+use super::{
+    cell::{Cell, ENCODED_CELL_SIZE},
+    chunk::PixelChunk,
+    chunk_grid::ChunkGrid,
+    chunk_handler::SimulationChunkContext,
+    explosion::Explosion,
+    generation::{generation_rng, ChunkGenerator, EmptyGenerator},
+    geometry_helpers::{BoundRect, DIRECTIONS},
+    material::{MaterialId, MaterialRegistry},
+    neighborhood::{ChunkNeighborhood, Execution, SharedChunks},
+    random::{chunk_rng, SimulationRng},
+};
+
+use rand::{prelude::SliceRandom, SeedableRng};
+
+// Smallest width and height of a chunk, moves between neighbors need at least one cell of reach into them
+pub const MIN_CHUNK_SIZE: u32 = 2;
+
+// Unloaded chunks kept by default, about 14 MB of cells with chunks of 64 by 64 cells
+pub const DEFAULT_STORED_CHUNK_LIMIT: usize = 256;
+
+// Pixel world component which holds the chunks, as well as general information
+#[derive(Component)]
+pub struct PixelWorld {
+    pub chunk_size: UVec2,
+    pub world_size: UVec2,
+    pub chunk_amount: UVec2,
+
+    // Loaded chunks, fixed size worlds hold the chunks from the origin up to the chunk amount
+    // Streaming worlds make the grid sparse so chunks can be loaded anywhere
+    pub chunks: ChunkGrid,
+
+    // Seed of all randomness in the simulation
+    pub seed: u64,
+    // Generator for changes outside of the chunk simulation, such as placing cells and the order of chunk updates
+    pub(super) rng: SimulationRng,
+
+    // Positions of solid cells which were destroyed, these are checked for floating islands
+    // Edits and explosions add to this when setting cells, the simulation when cells burn, react or change phase
+    pub(super) removed_solids: Vec<IVec2>,
+
+    // Areas simulated since liquid bodies were last equalized
+    pub(super) pressure_rects: HashMap<IVec2, BoundRect>,
+
+    // Explosions set off by the simulation, these are sent as events after the update
+    pub(super) explosions: Vec<Explosion>,
+
+    // Cells of chunks which were unloaded, encoded so they take up less memory than loaded chunks
+    pub(super) stored_chunks: HashMap<IVec2, Vec<u8>>,
+    // Most chunks kept in `stored_chunks`, past this the stored chunks farthest away are dropped and generated again when they are loaded
+    pub stored_chunk_limit: usize,
+
+    // Fills chunks which are created for the first time
+    generator: Box<dyn ChunkGenerator>,
+
+    pub(super) iteration: u32,
+
+    // How chunks are simulated, single threaded execution gives the same results and also runs under Miri
+    pub execution: Execution,
+}
+
+impl PixelWorld {
+    // Create a new pixel world based on the total size and how many chunks it should be subdivided into
+    // Worlds with the same seed give the same results when they get the same changes
+    pub fn new(world_size: UVec2, chunk_amount: UVec2, seed: u64) -> Self {
+        Self::with_generator(world_size, chunk_amount, seed, EmptyGenerator)
+    }
+
+    // Create a new pixel world whose chunks are filled by a generator when they are created
+    // Sizes which are not a multiple of the chunk amount round the chunk size up, the cells of the last chunks past the end of the world are never used
+    // The chunk amount is lowered if the last chunks would be outside of the world entirely
+    // Panics if the chunks would be less than 2 cells wide or high, cells could then reach past the neighbors they are simulated with
+    pub fn with_generator(
+        world_size: UVec2,
+        chunk_amount: UVec2,
+        seed: u64,
+        generator: impl ChunkGenerator + 'static,
+    ) -> Self {
+        let chunk_size = (world_size + chunk_amount - UVec2::ONE) / chunk_amount;
+        assert!(
+            chunk_size.min_element() >= MIN_CHUNK_SIZE,
+            "chunks of size {chunk_size} are too small, they must be at least {MIN_CHUNK_SIZE} cells wide and high"
+        );
+        let chunk_amount = (world_size + chunk_size - UVec2::ONE) / chunk_size;
+        let mut new_world = Self::without_chunks(
+            world_size,
+            chunk_amount,
+            chunk_size,
+            seed,
+            Box::new(generator),
+        );
+
+        // create chunks
+        for x in 0..new_world.chunk_amount.x {
+            for y in 0..new_world.chunk_amount.y {
+                new_world.create_chunk(x as i32, y as i32);
+            }
+        }
+
+        new_world
+    }
+
+    // Replaces the generator, only chunks created from now on are affected
+    pub fn set_generator(&mut self, generator: impl ChunkGenerator + 'static) {
+        self.generator = Box::new(generator);
+    }
+
+    pub(super) fn without_chunks(
+        world_size: UVec2,
+        chunk_amount: UVec2,
+        chunk_size: UVec2,
+        seed: u64,
+        generator: Box<dyn ChunkGenerator>,
+    ) -> Self {
+        PixelWorld {
+            chunk_amount,
+            world_size,
+            chunk_size,
+            chunks: ChunkGrid::dense(chunk_amount),
+            seed,
+            rng: SimulationRng::seed_from_u64(seed),
+            removed_solids: Vec::new(),
+            pressure_rects: HashMap::new(),
+            explosions: Vec::new(),
+            stored_chunks: HashMap::new(),
+            stored_chunk_limit: DEFAULT_STORED_CHUNK_LIMIT,
+            generator,
+            iteration: 0,
+            execution: Execution::default(),
+        }
+    }
+
+    // Return position of chunk and dirty rect
+    pub fn get_chunk_dirty_rects(&self) -> Vec<(IVec2, BoundRect)> {
+        self.chunks
+            .iter()
+            .map(|chunk| (chunk.position, chunk.current_dirty_rect))
+            .collect()
+    }
+
+    fn create_chunk(&mut self, x: i32, y: i32) {
+        let mut chunk = PixelChunk::new(self.chunk_size, IVec2 { x, y });
+        let mut rng = generation_rng(self.seed, chunk.position);
+        self.generator.generate(self.seed, &mut chunk, &mut rng);
+        chunk.clear_outside(self.chunk_extent(chunk.position));
+        self.chunks.insert(chunk);
+    }
+
+    // Loads the chunk at a position, restoring it if it was unloaded before or creating it otherwise
+    // Returns false if the chunk was already loaded, or if it is outside of a world whose chunk grid is not sparse
+    pub fn load_chunk(&mut self, position: IVec2) -> bool {
+        if self.chunks.contains(position) || !self.chunks.can_hold(position) {
+            return false;
+        }
+        let restored = self
+            .stored_chunks
+            .remove(&position)
+            .and_then(|bytes| PixelChunk::from_encoded_cells(self.chunk_size, position, &bytes));
+        match restored {
+            Some(chunk) => {
+                self.chunks.insert(chunk);
+            }
+            None => self.create_chunk(position.x, position.y),
+        }
+
+        // Cells next to the new chunk could not move into it before
+        self.wake_chunks_around(position);
+        true
+    }
+
+    // Marks a chunk and the chunks around it as dirty, so that all of their cells are simulated again
+    pub fn wake_chunks_around(&mut self, position: IVec2) {
+        for direction in DIRECTIONS {
+            if let Some(chunk) = self.chunk_mut(position + direction) {
+                chunk.wake();
+            }
+        }
+    }
+
+    // Hash of the cells of every chunk, including unloaded ones, for checking that two runs ended up the same
+    pub fn checksum(&self) -> u64 {
+        // FNV-1a, which gives the same hash on every platform and version
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut add = |bytes: &[u8]| {
+            for byte in bytes {
+                hash = (hash ^ *byte as u64).wrapping_mul(0x100000001b3);
+            }
+        };
+
+        let mut loaded: Vec<&PixelChunk> = self.chunks.iter().collect();
+        loaded.sort_by_key(|chunk| (chunk.position.y, chunk.position.x));
+        let mut stored: Vec<(&IVec2, &Vec<u8>)> = self.stored_chunks.iter().collect();
+        stored.sort_by_key(|(position, _)| (position.y, position.x));
+        for chunk in loaded {
+            add(&chunk.position.x.to_le_bytes());
+            add(&chunk.position.y.to_le_bytes());
+            add(&chunk.encode_cells());
+        }
+        for (position, bytes) in stored {
+            add(&position.x.to_le_bytes());
+            add(&position.y.to_le_bytes());
+            add(bytes);
+        }
+        hash
+    }
+
+    // Moves every cell over to a reloaded registry, including the cells of unloaded chunks, see `MaterialRegistry::remap_from`
+    // Loaded chunks are woken up, as their cells may behave differently and are drawn with the new colors
+    pub fn remap_materials(&mut self, remap: &[MaterialId], materials: &MaterialRegistry) {
+        for chunk in self.chunks.iter_mut() {
+            for cell in chunk.cells.iter_mut() {
+                cell.remap_material(remap, materials);
+            }
+            chunk.wake();
+        }
+        for bytes in self.stored_chunks.values_mut() {
+            let mut remapped = Vec::with_capacity(bytes.len());
+            for encoded in bytes.chunks_exact(ENCODED_CELL_SIZE) {
+                let mut cell = Cell::decode(encoded).unwrap_or_default();
+                cell.remap_material(remap, materials);
+                cell.encode(&mut remapped);
+            }
+            *bytes = remapped;
+        }
+    }
+
+    // Unloads the chunk at a position, its cells are stored and restored once it is loaded again
+    // Once more than `stored_chunk_limit` chunks are stored, those farthest from this one are forgotten
+    // Returns false if the chunk was not loaded
+    pub fn unload_chunk(&mut self, position: IVec2) -> bool {
+        let Some(chunk) = self.chunks.remove(position) else {
+            return false;
+        };
+        self.stored_chunks.insert(position, chunk.encode_cells());
+        self.pressure_rects.remove(&position);
+
+        while self.stored_chunks.len() > self.stored_chunk_limit {
+            // Ties are broken by position, so the same chunks are dropped on every run
+            let farthest =
+                self.stored_chunks.keys().copied().max_by_key(|stored| {
+                    ((*stored - position).length_squared(), stored.y, stored.x)
+                });
+            if let Some(farthest) = farthest {
+                self.stored_chunks.remove(&farthest);
+            }
+        }
+        true
+    }
+
+    // Where the cells of the world end, streaming worlds keep going in every direction
+    fn world_end(&self) -> Option<IVec2> {
+        (!self.chunks.is_sparse()).then_some(self.world_size.as_ivec2())
+    }
+
+    // Returns false for positions past the end of the world, which the last chunks of a world that does not divide evenly still cover
+    #[inline]
+    fn in_world(&self, position: IVec2) -> bool {
+        match self.world_end() {
+            Some(end) => position.cmplt(end).all(),
+            None => true,
+        }
+    }
+
+    // Size of the part of a chunk inside of the world, this is smaller than the chunk size for the last chunks of worlds that do not divide evenly
+    pub fn chunk_extent(&self, position: IVec2) -> UVec2 {
+        match self.world_end() {
+            Some(end) => (end - position * self.chunk_size.as_ivec2())
+                .clamp(IVec2::ZERO, self.chunk_size.as_ivec2())
+                .as_uvec2(),
+            None => self.chunk_size,
+        }
+    }
+
+    pub fn get_chunk_width(&self) -> u32 {
+        self.chunk_size.x
+    }
+
+    pub fn get_chunk_height(&self) -> u32 {
+        self.chunk_size.y
+    }
+
+    // Loaded chunks in the order of their indices, which stay the same while the chunks are loaded
+    pub fn get_chunks(&self) -> Vec<&PixelChunk> {
+        self.chunks.iter().collect()
+    }
+
+    #[inline]
+    fn chunk(&self, position: IVec2) -> Option<&PixelChunk> {
+        self.chunks.get(position)
+    }
+
+    // Returns true if the chunk has updated and needs to be rendered again
+    pub fn should_render(&self, position: IVec2) -> bool {
+        self.chunk(position).is_some_and(|c| c.should_update())
+    }
+
+    // Writes the colors of a chunk into the RGBA bytes of its image
+    pub fn render_chunk(&self, position: IVec2, data: &mut [u8], materials: &MaterialRegistry) {
+        if let Some(c) = self.chunk(position) {
+            c.render_chunk(data, materials);
+        }
+    }
+
+    /// Gets all the chunks that should update and returns their positions
+    /// The positions are sorted, so dense and sparse grids update in the same order
+    fn all_chunk_pos_should_update(&self) -> Vec<IVec2> {
+        let mut positions: Vec<IVec2> = self
+            .chunks
+            .iter()
+            .filter(|chunk| chunk.should_update())
+            .map(|chunk| chunk.position)
+            .collect();
+        positions.sort_by_key(|pos| (pos.y, pos.x));
+        positions
+    }
+
+    #[inline]
+    fn chunk_mut(&mut self, position: IVec2) -> Option<&mut PixelChunk> {
+        self.chunks.get_mut(position)
+    }
+
+    // Finds the chunk of a given world coordinate
+    pub fn cell_to_chunk_position(chunk_size: UVec2, position: IVec2) -> IVec2 {
+        position.div_euclid(chunk_size.as_ivec2())
+    }
+
+    // Finds the cell position inside of a chunk given a world cell coordinate
+    pub fn cell_to_position_in_chunk(chunk_size: UVec2, position: IVec2) -> IVec2 {
+        let chunk_position = Self::cell_to_chunk_position(chunk_size, position);
+
+        position - chunk_position * chunk_size.as_ivec2()
+    }
+
+    // Get a cell based on it's world coordinate
+    pub fn get_cell(&self, position: IVec2) -> Option<Cell> {
+        if !self.in_world(position) {
+            return None;
+        }
+        let chunk = self.chunk(Self::cell_to_chunk_position(self.chunk_size, position))?;
+
+        let local = Self::cell_to_position_in_chunk(self.chunk_size, position);
+        Some(chunk.get_cell(local))
+    }
+
+    // Finds if the cell is inside a dirty rectangle of a chunk
+    pub fn cell_inside_dirty(&self, position: IVec2) -> bool {
+        if !self.in_world(position) {
+            return false;
+        }
+        let chunk = self.chunk(Self::cell_to_chunk_position(self.chunk_size, position));
+
+        if let Some(chunk) = chunk {
+            let local = Self::cell_to_position_in_chunk(self.chunk_size, position);
+
+            return chunk.current_dirty_rect.contains(&local);
+        }
+        false
+    }
+
+    // Sets the value of a cell in this chunk, if it exists.
+    // Makes sure that the chunk is marked as dirty if it wasn't already.
+    pub fn set_cell(&mut self, position: IVec2, cell: Cell) {
+        if !self.in_world(position) {
+            return;
+        }
+        let chunk_size = self.chunk_size;
+        let Some(chunk) = self.chunk_mut(Self::cell_to_chunk_position(chunk_size, position)) else {
+            return;
+        };
+
+        let local = Self::cell_to_position_in_chunk(chunk_size, position);
+        let removes_solid = chunk.get_cell(local).physics.is_solid() && !cell.physics.is_solid();
+        chunk.set_cell(local.x, local.y, cell);
+
+        chunk.render_override = 3;
+
+        if removes_solid {
+            self.removed_solids.push(position);
+        }
+    }
+
+    // Places a new cell of the material, the world's generator picks its shade
+    pub fn set_material(
+        &mut self,
+        position: IVec2,
+        material: MaterialId,
+        materials: &MaterialRegistry,
+    ) {
+        let cell = Cell::new(material, materials, &mut self.rng);
+        self.set_cell(position, cell);
+    }
+
+    // Generator for changes outside of the chunk simulation
+    pub fn rng(&mut self) -> &mut SimulationRng {
+        &mut self.rng
+    }
+
+    // Takes the explosions set off by explosive materials since this was last called
+    pub fn take_explosions(&mut self) -> Vec<Explosion> {
+        std::mem::take(&mut self.explosions)
+    }
+
+    // Main update function, simulates every chunk that is awake once
+    // Chunks are simulated on the compute task pool, which is created if no app has set it up yet, unless execution is single threaded
+    pub fn step(&mut self, materials: &MaterialRegistry) {
+        let all_pos = self.all_chunk_pos_should_update();
+        let chunk_size = self.chunk_size;
+        let world_end = self.world_end();
+
+        // Areas that are simulated in this update, liquid bodies in these are equalized afterwards
+        let simulated_rects: Vec<(IVec2, BoundRect)> = all_pos
+            .iter()
+            .filter_map(|pos| {
+                let ch = self.chunk(*pos)?;
+                Some((*pos, ch.current_dirty_rect.union(&ch.previous_dirty_rect)))
+            })
+            .collect();
+
+        // Shuffling the order of updates to avoid bias
+        // It makes large amounts of movements between chunks feel a bit more natural instead of favoring one direction of movement
+        let mut phases = [
+            IVec2::new(0, 0),
+            IVec2::new(1, 0),
+            IVec2::new(0, 1),
+            IVec2::new(1, 1),
+        ];
+        phases.shuffle(&mut self.rng);
+        let (seed, iteration) = (self.seed, self.iteration);
+
+        for pos in &all_pos {
+            if let Some(ch) = self.chunk_mut(*pos) {
+                ch.commit_cells_unupdated();
+            }
+        }
+
+        // Simulates a chunk by creating the context for simulation
+        // Returns the updates to the dirty rects, along with any explosions that were set off and the solids that were destroyed
+        let rects: HashMap<IVec2, BoundRect> = simulated_rects.iter().cloned().collect();
+        let simulate = |(pos, neighborhood): (IVec2, ChunkNeighborhood)| {
+            let rng = chunk_rng(seed, iteration, pos);
+            let end = world_end.map_or(IVec2::MAX, |end| end - pos * chunk_size.as_ivec2());
+            let mut scc = SimulationChunkContext::new(
+                pos,
+                neighborhood,
+                rects[&pos],
+                end,
+                chunk_size,
+                materials,
+                rng,
+            );
+            let (updates, explosions, removed_solids) = scc.simulate();
+            (pos, updates, explosions, removed_solids)
+        };
+        let simulate = &simulate;
+
+        // Each phase finishes before the next one starts, chunks of a single phase are never next to each other
+        let mut results = Vec::new();
+        let mut shared = SharedChunks::new(&mut self.chunks, chunk_size);
+        for phase in phases {
+            let neighborhoods = shared.phase(phase, &all_pos);
+            match self.execution {
+                Execution::Parallel => results.extend(
+                    ComputeTaskPool::get_or_init(TaskPool::default).scope(|scope| {
+                        for neighborhood in neighborhoods {
+                            scope.spawn(async move { simulate(neighborhood) });
+                        }
+                    }),
+                ),
+                Execution::SingleThreaded => {
+                    results.extend(neighborhoods.into_iter().map(simulate))
+                }
+            }
+        }
+        drop(shared);
+
+        // Sorting the results keeps the explosions in the same order whatever order the phases ran in
+        results.sort_by_key(|(pos, _, _, _)| (pos.y, pos.x));
+
+        // Merge all of the dirty rect updates
+        let mut dirty_rect_updates: HashMap<IVec2, Vec<IVec2>> = HashMap::new();
+        for (_, new_update, explosions, removed_solids) in results {
+            self.explosions.extend(explosions);
+            self.removed_solids.extend(removed_solids);
+            for (position, cells) in new_update {
+                if let Some(existing) = dirty_rect_updates.get_mut(&position) {
+                    existing.extend(cells);
+                } else {
+                    dirty_rect_updates.insert(position, cells);
+                }
+            }
+        }
+
+        // Apply dirty rect updates
+        for (position, cells) in dirty_rect_updates {
+            if let Some(ch) = self.chunk_mut(position) {
+                ch.swap_rects();
+                ch.construct_dirty_rect(&cells);
+            }
+        }
+
+        // Runs after all chunks have been simulated, it can move liquid across any chunk
+        self.equalize_liquids(&simulated_rects);
+        self.iteration += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{IVec2, UVec2};
+
+    use crate::{
+        cell::{Cell, PhysicsType, Temperature, AMBIENT_TEMPERATURE},
+        explosion::Explosion,
+        history::EditHistory,
+        material::{MaterialDefinitions, MaterialRegistry},
+        neighborhood::Execution,
+    };
+
+    use super::PixelWorld;
+
+    fn registry() -> MaterialRegistry {
+        let definitions = MaterialDefinitions::from_ron(
+            br#"(materials: [
+                (name: "Sand", color: (230, 195, 92, 255), physics: SoftSolid, density: 1.6),
+                (name: "Water", color: (20, 125, 205, 150), physics: Liquid, density: 1.0),
+                (name: "Stone", color: (120, 120, 120, 255), physics: HardSolid, conductivity: 0.5),
+            ])"#,
+        )
+        .unwrap();
+        MaterialRegistry::from_definitions(&definitions)
+    }
+
+    fn count(world: &PixelWorld) -> usize {
+        world
+            .chunks
+            .iter()
+            .flat_map(|chunk| chunk.cells.iter())
+            .filter(|cell| !cell.is_empty())
+            .count()
+    }
+
+    #[test]
+    fn positions_in_non_square_chunks() {
+        let chunk_size = UVec2::new(34, 15);
+        let position = IVec2::new(70, 20);
+        assert_eq!(
+            PixelWorld::cell_to_chunk_position(chunk_size, position),
+            IVec2::new(2, 1)
+        );
+        assert_eq!(
+            PixelWorld::cell_to_position_in_chunk(chunk_size, position),
+            IVec2::new(2, 5)
+        );
+        assert_eq!(
+            PixelWorld::cell_to_position_in_chunk(chunk_size, IVec2::new(-1, -1)),
+            IVec2::new(33, 14)
+        );
+    }
+
+    #[test]
+    fn sizes_which_do_not_divide_evenly() {
+        let world = PixelWorld::new(UVec2::new(100, 30), UVec2::new(3, 2), 1);
+        assert_eq!(world.chunk_size, UVec2::new(34, 15));
+        assert_eq!(world.chunk_amount, UVec2::new(3, 2));
+        assert_eq!(world.chunk_extent(IVec2::new(2, 1)), UVec2::new(32, 15));
+        assert_eq!(world.chunk_extent(IVec2::new(0, 0)), UVec2::new(34, 15));
+
+        assert!(world.get_cell(IVec2::new(99, 29)).is_some());
+        assert!(world.get_cell(IVec2::new(100, 0)).is_none());
+        assert!(world.get_cell(IVec2::new(0, 30)).is_none());
+
+        // Chunks which would only hold cells past the end of the world are left out
+        let world = PixelWorld::new(UVec2::new(5, 7), UVec2::new(4, 4), 1);
+        assert_eq!(world.chunk_size, UVec2::new(2, 2));
+        assert_eq!(world.chunk_amount, UVec2::new(3, 4));
+        assert_eq!(world.chunks.len(), 12);
+        assert_eq!(world.chunk_extent(IVec2::new(2, 3)), UVec2::new(1, 1));
+    }
+
+    #[test]
+    #[should_panic(expected = "too small")]
+    fn chunks_of_a_single_cell_are_rejected() {
+        PixelWorld::new(UVec2::new(8, 8), UVec2::new(8, 4), 1);
+    }
+
+    #[test]
+    fn cells_stay_inside_of_odd_sized_worlds() {
+        let materials = registry();
+        let sand = materials.id("Sand").unwrap();
+        let water = materials.id("Water").unwrap();
+        let mut world = PixelWorld::new(UVec2::new(37, 23), UVec2::new(4, 3), 1);
+        world.execution = Execution::SingleThreaded;
+
+        // Setting cells past the end of the world does nothing
+        world.set_material(IVec2::new(38, 5), sand, &materials);
+        world.set_cell(IVec2::new(5, 23), Cell::default());
+        assert_eq!(count(&world), 0);
+
+        for y in 10..22 {
+            world.set_material(IVec2::new(35, y), water, &materials);
+            world.set_material(IVec2::new(36, y), sand, &materials);
+        }
+        let placed = count(&world);
+        for _ in 0..100 {
+            world.step(&materials);
+        }
+        assert_eq!(count(&world), placed);
+
+        let end = world.world_size.as_ivec2();
+        for chunk in world.chunks.iter() {
+            let origin = chunk.position * world.chunk_size.as_ivec2();
+            for (index, cell) in chunk.cells.iter().enumerate() {
+                let local = IVec2::new(
+                    index as i32 % chunk.size.x as i32,
+                    index as i32 / chunk.size.x as i32,
+                );
+                let inside = (origin + local).cmplt(end).all();
+                assert!(inside || cell.is_empty());
+            }
+        }
+        // The water has spread along the floor instead of leaking past the right edge
+        assert!(!world.get_cell(IVec2::new(0, 0)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parallel_and_single_threaded_execution_agree() {
+        let materials = registry();
+        let sand = materials.id("Sand").unwrap();
+        let water = materials.id("Water").unwrap();
+        let stone = materials.id("Stone").unwrap();
+        let run = |execution| {
+            let mut world = PixelWorld::new(UVec2::new(64, 64), UVec2::new(4, 4), 3);
+            world.execution = execution;
+            // Cells falling across the borders of every chunk, around a hot stone ledge
+            for x in 4..60 {
+                world.set_material(IVec2::new(x, 30), stone, &materials);
+                for y in 40..48 {
+                    let material = if x % 3 == 0 { water } else { sand };
+                    world.set_material(IVec2::new(x, y), material, &materials);
+                }
+            }
+            let ledge = world.get_cell(IVec2::new(30, 30)).unwrap();
+            world.set_cell(
+                IVec2::new(30, 30),
+                Cell {
+                    temperature: Temperature::from_celsius(400.),
+                    ..ledge
+                },
+            );
+            for _ in 0..60 {
+                world.step(&materials);
+            }
+            world.checksum()
+        };
+        assert_eq!(run(Execution::Parallel), run(Execution::SingleThreaded));
+    }
+
+    #[test]
+    fn reloaded_chunks_keep_their_cells() {
+        let materials = registry();
+        let sand = materials.id("Sand").unwrap();
+        let water = materials.id("Water").unwrap();
+        let mut world = PixelWorld::new(UVec2::new(32, 32), UVec2::new(2, 2), 1);
+        world.execution = Execution::SingleThreaded;
+        world.chunks.make_sparse();
+        for x in 16..32 {
+            world.set_material(IVec2::new(x, 28), sand, &materials);
+            world.set_material(IVec2::new(x, 20), water, &materials);
+        }
+        for _ in 0..5 {
+            world.step(&materials);
+        }
+
+        let position = IVec2::new(1, 1);
+        let cells = world.chunks.get(position).unwrap().encode_cells();
+        let checksum = world.checksum();
+        assert!(world.unload_chunk(position));
+        assert!(world.get_cell(IVec2::new(20, 20)).is_none());
+        assert!(world.load_chunk(position));
+        assert_eq!(world.chunks.get(position).unwrap().encode_cells(), cells);
+        assert_eq!(world.checksum(), checksum);
+    }
+
+    #[test]
+    fn far_stored_chunks_are_dropped_past_the_limit() {
+        let materials = registry();
+        let sand = materials.id("Sand").unwrap();
+        let mut world = PixelWorld::new(UVec2::new(32, 8), UVec2::new(4, 1), 1);
+        world.chunks.make_sparse();
+        world.stored_chunk_limit = 2;
+        for x in 0..16 {
+            world.set_material(IVec2::new(x, 0), sand, &materials);
+        }
+
+        for x in [0, 1, 3] {
+            assert!(world.unload_chunk(IVec2::new(x, 0)));
+        }
+        assert_eq!(world.stored_chunks.len(), 2);
+
+        // The first chunk was farthest from the last one unloaded, it is generated again without its sand
+        world.load_chunk(IVec2::new(0, 0));
+        world.load_chunk(IVec2::new(1, 0));
+        assert!(world.get_cell(IVec2::new(0, 0)).unwrap().is_empty());
+        assert!(!world.get_cell(IVec2::new(8, 0)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn heat_moves_once_between_neighbors() {
+        let materials = registry();
+        let stone = materials.id("Stone").unwrap();
+        let mut world = PixelWorld::new(UVec2::new(16, 16), UVec2::new(2, 2), 1);
+        world.execution = Execution::SingleThreaded;
+
+        // A hot cell next to a cold one, at the edge of two chunks
+        let hot = IVec2::new(7, 4);
+        let cold = IVec2::new(8, 4);
+        world.set_material(hot, stone, &materials);
+        world.set_material(cold, stone, &materials);
+        let cell = world.get_cell(hot).unwrap();
+        world.set_cell(
+            hot,
+            Cell {
+                temperature: Temperature::from_celsius(500.),
+                ..cell
+            },
+        );
+
+        world.step(&materials);
+        let hot = world.get_cell(hot).unwrap().temperature.celsius();
+        let cold = world.get_cell(cold).unwrap().temperature.celsius();
+        // One exchange between the pair, along with a little heat lost to the air around them
+        let ambient = AMBIENT_TEMPERATURE.celsius();
+        let flow = (500. - ambient) * 0.5 * 0.125;
+        assert!((cold - ambient - flow).abs() < 1.);
+        assert!((500. - hot - flow).abs() < 2.);
+    }
+
+    #[test]
+    fn moving_cells_exchange_heat_once_per_update() {
+        let definitions = MaterialDefinitions::from_ron(
+            br#"(materials: [
+                (name: "Steam", color: (220, 220, 250, 150), physics: Gas, density: 0.5, conductivity: 0.5),
+            ])"#,
+        )
+        .unwrap();
+        let materials = MaterialRegistry::from_definitions(&definitions);
+        let steam = materials.id("Steam").unwrap();
+        let mut world = PixelWorld::new(UVec2::new(16, 16), UVec2::new(1, 1), 1);
+        world.execution = Execution::SingleThreaded;
+
+        // Hot steam rises into rows which have not been updated yet
+        let start = IVec2::new(8, 2);
+        world.set_material(start, steam, &materials);
+        let cell = world.get_cell(start).unwrap();
+        world.set_cell(
+            start,
+            Cell {
+                temperature: Temperature::from_celsius(500.),
+                ..cell
+            },
+        );
+        world.step(&materials);
+
+        let (position, cell) = (0..16)
+            .flat_map(|y| (0..16).map(move |x| IVec2::new(x, y)))
+            .find_map(|position| {
+                let cell = world.get_cell(position)?;
+                (!cell.is_empty()).then_some((position, cell))
+            })
+            .unwrap();
+        assert!(position.y > start.y);
+        // Heat is lost to the four neighbors once, not again after moving
+        let kept = (1. - 0.01 * 0.125_f32).powi(4);
+        let ambient = AMBIENT_TEMPERATURE.celsius();
+        let expected = ambient + (500. - ambient) * kept;
+        // Every exchange is rounded down to a sixteenth of a degree
+        assert!((cell.temperature.celsius() - expected).abs() < 0.25);
+    }
+
+    #[test]
+    fn undoing_an_explosion_leaves_thrown_cells_out() {
+        let materials = registry();
+        let sand = materials.id("Sand").unwrap();
+        let stone = materials.id("Stone").unwrap();
+        let mut world = PixelWorld::new(UVec2::new(32, 32), UVec2::new(2, 2), 1);
+        world.execution = Execution::SingleThreaded;
+        for y in 0..12 {
+            for x in 0..32 {
+                let material = if y < 6 { stone } else { sand };
+                world.set_material(IVec2::new(x, y), material, &materials);
+            }
+        }
+        let placed = count(&world);
+
+        let mut history = EditHistory::default();
+        let explosion = Explosion {
+            center: IVec2::new(16, 6),
+            radius: 5.,
+            force: 10.,
+            undoable: true,
+        };
+        let thrown = history.explode(&mut world, &explosion, &materials).len();
+        assert!(thrown > 0);
+        assert!(count(&world) + thrown < placed);
+
+        // The destroyed stone comes back, the sand stays gone as it was thrown as particles
+        assert!(history.undo(&mut world));
+        assert_eq!(count(&world) + thrown, placed);
+    }
+
+    #[test]
+    fn stone_resting_on_sand_is_not_floating() {
+        let materials = registry();
+        let sand = materials.id("Sand").unwrap();
+        let water = materials.id("Water").unwrap();
+        let stone = materials.id("Stone").unwrap();
+        let mut world = PixelWorld::new(UVec2::new(32, 32), UVec2::new(2, 2), 1);
+        world.execution = Execution::SingleThreaded;
+
+        // A stone slab on a bed of sand and another one on a pool of water, away from the sides of the world
+        for x in 2..30 {
+            for y in 0..4 {
+                let bed = if x < 16 { sand } else { water };
+                world.set_material(IVec2::new(x, y), bed, &materials);
+            }
+        }
+        for x in 0..32 {
+            for y in 4..8 {
+                if !(14..18).contains(&x) {
+                    world.set_material(IVec2::new(x, y), stone, &materials);
+                }
+            }
+        }
+        // Cutting the slabs off from the sides of the world
+        for y in 4..8 {
+            world.set_cell(IVec2::new(4, y), Cell::default());
+            world.set_cell(IVec2::new(27, y), Cell::default());
+        }
+
+        let islands = world.take_floating_islands();
+        assert_eq!(islands.len(), 1);
+        assert_eq!(islands[0].position, IVec2::new(18, 4));
+        assert!(world.get_cell(IVec2::new(10, 5)).unwrap().physics == PhysicsType::HardSolid);
+    }
+
+    #[test]
+    fn stone_held_up_by_melting_ice_is_floating() {
+        let definitions = MaterialDefinitions::from_ron(
+            br#"(materials: [
+                (name: "Stone", color: (120, 120, 120, 255), physics: HardSolid),
+                (name: "Ice", color: (180, 220, 250, 255), physics: HardSolid, transitions: [(when: Above(0.0), into: "Water")]),
+                (name: "Water", color: (20, 125, 205, 150), physics: Liquid, density: 1.0),
+            ])"#,
+        )
+        .unwrap();
+        let materials = MaterialRegistry::from_definitions(&definitions);
+        let stone = materials.id("Stone").unwrap();
+        let ice = materials.id("Ice").unwrap();
+        let mut world = PixelWorld::new(UVec2::new(16, 16), UVec2::new(2, 2), 1);
+        world.execution = Execution::SingleThreaded;
+
+        // A stone slab on a pillar of ice, which melts in the warm air
+        for y in 0..4 {
+            world.set_material(IVec2::new(8, y), ice, &materials);
+        }
+        for x in 4..12 {
+            world.set_material(IVec2::new(x, 4), stone, &materials);
+        }
+        assert!(world.take_floating_islands().is_empty());
+
+        world.step(&materials);
+        let islands = world.take_floating_islands();
+        assert_eq!(islands.len(), 1);
+        assert_eq!(islands[0].position, IVec2::new(4, 4));
+        assert!(world.get_cell(IVec2::new(6, 4)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reloaded_materials_take_their_new_physics() {
+        let old = registry();
+        let water = old.id("Water").unwrap();
+        let mut world = PixelWorld::new(UVec2::new(16, 16), UVec2::new(2, 2), 1);
+        world.set_material(IVec2::new(3, 3), water, &old);
+
+        // Water turned into a soft solid and moved to another id
+        let new = MaterialRegistry::from_definitions(
+            &MaterialDefinitions::from_ron(
+                br#"(materials: [
+                    (name: "Sand", color: (230, 195, 92, 255), physics: SoftSolid, density: 1.6),
+                    (name: "Mud", color: (90, 60, 30, 255), physics: SoftSolid, density: 1.8),
+                    (name: "Water", color: (20, 125, 205, 150), physics: SoftSolid, density: 1.0),
+                ])"#,
+            )
+            .unwrap(),
+        );
+        world.remap_materials(&new.remap_from(&old), &new);
+        let cell = world.get_cell(IVec2::new(3, 3)).unwrap();
+        assert_eq!(cell.material, new.id("Water").unwrap());
+        assert_eq!(cell.physics, PhysicsType::SoftSolid);
+    }
+}
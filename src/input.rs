@@ -7,7 +7,11 @@ pub(super) fn plugin(app: &mut App) {
     app.insert_resource(InteractionInformation::default());
     app.add_systems(
         Update,
-        (get_position, handle_keyboard_input).run_if(in_state(Screen::Playing)),
+        (
+            get_position.run_if(any_with_component::<PrimaryWindow>),
+            handle_keyboard_input,
+        )
+            .run_if(in_state(Screen::Playing)),
     );
 }
 
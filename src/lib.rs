@@ -5,24 +5,43 @@ mod pixel;
 mod rigid;
 
 mod input;
+mod replay;
+mod save;
+mod simulation;
 
 mod dev_tools;
 mod screen;
 mod states;
 pub mod ui;
 
+use std::{path::PathBuf, time::Duration};
+
 use bevy::{
+    app::ScheduleRunnerPlugin,
     ecs::{system::RunSystemOnce, world::Command},
     prelude::*,
-    window::PresentMode,
+    render::{settings::WgpuSettings, RenderPlugin},
+    time::TimeUpdateStrategy,
+    window::{ExitCondition, PresentMode},
+    winit::WinitPlugin,
 };
 use bevy_egui::EguiPlugin;
 use particles::ParticlePlugin;
 use pixel::{spawn_pixel_world, PixelPlugin};
+use replay::{PendingReplay, Recording};
 use rigid::{spawn_rigid_world, SandEngineRigidPlugin};
 use states::{AppSet, DebugState};
 
-pub struct AppPlugin;
+// Rate of the fixed update, which runs the simulation
+const FIXED_UPDATE_HZ: f64 = 64.;
+
+#[derive(Default)]
+pub struct AppPlugin {
+    // Recording to play back once the game has loaded
+    pub replay: Option<PathBuf>,
+    // Runs without a window or renderer, one fixed update per frame as fast as possible, and quits once the replay is finished
+    pub headless: bool,
+}
 
 impl Plugin for AppPlugin {
     fn build(&self, app: &mut App) {
@@ -31,43 +50,84 @@ impl Plugin for AppPlugin {
             (AppSet::TickTimers, AppSet::RecordInput, AppSet::Update).chain(),
         );
 
-        app.add_plugins((
+        let default_plugins = if self.headless {
             DefaultPlugins
                 .set(WindowPlugin {
-                    primary_window: Some(Window {
-                        title: "Pixel Simulation".to_string(),
-                        present_mode: PresentMode::AutoVsync,
-                        canvas: Some("#bevy".to_string()),
-                        fit_canvas_to_parent: true,
-                        prevent_default_event_handling: true,
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    ..default()
+                })
+                .set(RenderPlugin {
+                    render_creation: WgpuSettings {
+                        backends: None,
                         ..default()
-                    }),
+                    }
+                    .into(),
                     ..default()
                 })
-                .set(ImagePlugin::default_nearest()),
+                .disable::<WinitPlugin>()
+        } else {
+            DefaultPlugins.set(WindowPlugin {
+                primary_window: Some(Window {
+                    title: "Pixel Simulation".to_string(),
+                    present_mode: PresentMode::AutoVsync,
+                    canvas: Some("#bevy".to_string()),
+                    fit_canvas_to_parent: true,
+                    prevent_default_event_handling: true,
+                    ..default()
+                }),
+                ..default()
+            })
+        };
+        app.add_plugins((
+            default_plugins.set(ImagePlugin::default_nearest()),
             EguiPlugin,
             dev_tools::plugin,
-        ))
-        .init_state::<DebugState>()
-        .init_state::<WorldSizes>()
-        .insert_resource(Time::<Fixed>::from_hz(64.))
-        .add_plugins(input::plugin)
-        .add_plugins((ui::plugin, screen::plugin))
-        .add_plugins(PixelPlugin)
-        .add_plugins(SandEngineRigidPlugin)
-        .add_plugins(ParticlePlugin);
+        ));
+        if self.headless {
+            app.add_plugins(ScheduleRunnerPlugin::run_loop(Duration::ZERO))
+                .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+                    1. / FIXED_UPDATE_HZ,
+                )));
+        }
+        if let Some(path) = &self.replay {
+            app.insert_resource(PendingReplay {
+                path: path.clone(),
+                exit_when_finished: self.headless,
+            });
+        }
+
+        app.init_state::<DebugState>()
+            .init_state::<WorldSizes>()
+            .init_resource::<FixedWorldTerrain>()
+            .insert_resource(Time::<Fixed>::from_hz(FIXED_UPDATE_HZ))
+            .add_plugins(input::plugin)
+            .add_plugins((ui::plugin, screen::plugin))
+            .add_plugins(PixelPlugin)
+            .add_plugins(SandEngineRigidPlugin)
+            .add_plugins(ParticlePlugin)
+            .add_plugins((replay::plugin, save::plugin, simulation::plugin));
     }
 }
 
 /// A command to spawn the worlds
-#[derive(Debug, Clone, Copy)]
+/// It is kept as a resource while playing, so the settings of the current worlds can be looked up
+#[derive(Resource, Debug, Clone, Copy)]
 pub struct SpawnWorlds {
     pub world_size: UVec2,
     pub chunk_amount: UVec2,
+    // Seed of the pixel simulation, spawning with the same seed lets a run be reproduced
+    pub seed: u64,
+    // Streams chunks in around the camera and player instead of keeping a fixed grid of chunks
+    pub streaming: bool,
+    // Fills new chunks with generated terrain instead of leaving them empty
+    pub terrain: bool,
 }
 
 impl Command for SpawnWorlds {
     fn apply(self, world: &mut World) {
+        world.insert_resource(self);
+        world.insert_resource(Recording::new(self));
         world.run_system_once_with(self, spawn_pixel_world);
 
         world.run_system_once_with(self, spawn_rigid_world);
@@ -81,16 +141,36 @@ pub enum WorldSizes {
     #[default]
     Medium,
     Large,
+    // Much wider than it is tall, with chunks that are neither square nor a divisor of the world size
+    Wide,
+    // Starts like a regular world but keeps going in every direction
+    Infinite,
 }
 
-pub fn spawn_worlds(commands: &mut Commands, world_size: Res<State<WorldSizes>>) {
+// Whether fixed size worlds are spawned with terrain, toggled on the title screen
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FixedWorldTerrain(pub bool);
+
+pub fn spawn_worlds(
+    commands: &mut Commands,
+    world_size: Res<State<WorldSizes>>,
+    terrain: Res<FixedWorldTerrain>,
+) {
+    let streaming = *world_size.get() == WorldSizes::Infinite;
     let (world_size, chunk_amount) = match *world_size.get() {
         WorldSizes::Small => (UVec2::new(128, 128), UVec2::new(2, 2)),
-        WorldSizes::Medium => (UVec2::new(256, 256), UVec2::new(4, 4)),
+        WorldSizes::Medium | WorldSizes::Infinite => (UVec2::new(256, 256), UVec2::new(4, 4)),
         WorldSizes::Large => (UVec2::new(512, 512), UVec2::new(8, 8)),
+        WorldSizes::Wide => (UVec2::new(720, 200), UVec2::new(10, 3)),
     };
+    let seed = rand::random();
+    info!("Spawning worlds with seed {seed}");
     commands.add(SpawnWorlds {
         world_size,
         chunk_amount,
+        seed,
+        streaming,
+        // Infinite worlds would be an endless void without something to stand on, they always have terrain
+        terrain: streaming || terrain.0,
     });
 }
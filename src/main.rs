@@ -1,6 +1,30 @@
 use bevy::prelude::*;
 use sandengine::AppPlugin;
 
+const USAGE: &str = "Usage: sandengine [--replay <file> [--headless]]";
+
 fn main() -> AppExit {
-    App::new().add_plugins(AppPlugin).run()
+    let mut plugin = AppPlugin::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--replay" => match args.next() {
+                Some(path) => plugin.replay = Some(path.into()),
+                None => return usage(),
+            },
+            "--headless" => plugin.headless = true,
+            _ => return usage(),
+        }
+    }
+    // Without a window the only thing to do is play back a recording
+    if plugin.headless && plugin.replay.is_none() {
+        return usage();
+    }
+
+    App::new().add_plugins(plugin).run()
+}
+
+fn usage() -> AppExit {
+    eprintln!("{USAGE}");
+    AppExit::error()
 }
@@ -9,11 +9,13 @@ use particle::{Particle, PARTICLE_GRAVITY};
 use crate::{
     pixel::{
         cell::{Cell, PhysicsType},
+        material::{MaterialRegistry, MaterialsReloaded, RemapMaterials},
         update_pixel_simulation,
         world::PixelWorld,
     },
     rigid::dynamic_entity::unfill_pixel_component,
     screen::Screen,
+    simulation::{simulation_running, SimulationControl},
 };
 
 /// Particle plugin
@@ -28,18 +30,49 @@ impl Plugin for ParticlePlugin {
             update_particles
                 .after(update_pixel_simulation)
                 .before(unfill_pixel_component)
-                .run_if(in_state(Screen::Playing)),
-        );
+                .run_if(in_state(Screen::Playing).and_then(simulation_running)),
+        )
+        .add_systems(Update, remap_particle_materials.in_set(RemapMaterials));
+    }
+}
+
+// Moves particles over to the registry when the materials are reloaded, particles of removed materials are gone
+fn remap_particle_materials(
+    mut commands: Commands,
+    mut events: EventReader<MaterialsReloaded>,
+    mut particles: Query<(Entity, &mut Particle)>,
+    registry: Res<MaterialRegistry>,
+) {
+    for MaterialsReloaded { remap } in events.read() {
+        for (entity, mut particle) in particles.iter_mut() {
+            let mut cell = Cell::from(*particle);
+            cell.remap_material(remap, &registry);
+            if cell.is_empty() {
+                commands.entity(entity).despawn();
+                continue;
+            }
+            *particle = Particle {
+                velocity: particle.velocity,
+                ..Particle::from(cell)
+            };
+        }
     }
 }
 
 // Spawn a particle into the ecs world
-pub fn spawn_particle(commands: &mut Commands, cell: &Cell, velocity: Vec2, position: Vec2) {
+pub fn spawn_particle(
+    commands: &mut Commands,
+    cell: &Cell,
+    materials: &MaterialRegistry,
+    velocity: Vec2,
+    position: Vec2,
+) {
+    let [r, g, b, _] = materials.cell_color(cell);
     commands.spawn((
         Particle::from_cell_with_velocity_position(cell, velocity),
         SpriteBundle {
             sprite: Sprite {
-                color: Color::srgba_u8(cell.color[0], cell.color[1], cell.color[2], 255),
+                color: Color::srgba_u8(r, g, b, 255),
                 custom_size: Some(Vec2::new(1., 1.)),
                 ..default()
             },
@@ -55,12 +88,17 @@ pub fn update_particles(
     mut commands: Commands,
     mut particles: Query<(&mut Particle, &mut Transform, Entity)>,
     mut pxl: Query<&mut PixelWorld>,
+    control: Res<SimulationControl>,
 ) {
     let world = &mut pxl.single_mut();
 
     for (mut particle, mut transform, entity) in particles.iter_mut() {
-        if apply_velocity(&mut particle, &mut transform, world) {
-            commands.entity(entity).despawn();
+        // One velocity step for every step of the pixel simulation
+        for _ in 0..control.steps() {
+            if apply_velocity(&mut particle, &mut transform, world) {
+                commands.entity(entity).despawn();
+                break;
+            }
         }
     }
 }
@@ -73,16 +111,13 @@ fn apply_velocity(
 ) -> bool {
     // If the velocity is small, remove the particle
     if particle.velocity.length() < 0.4 {
-        world.set_cell_external(
-            transform.translation.xy().as_ivec2(),
-            Cell::from(particle.clone()),
-        );
+        world.set_cell(transform.translation.xy().as_ivec2(), Cell::from(*particle));
         return true;
     }
 
     // Add gravity based on physics
     match particle.physics {
-        PhysicsType::Gas(_) => particle.velocity.y += PARTICLE_GRAVITY,
+        PhysicsType::Gas => particle.velocity.y += PARTICLE_GRAVITY,
         _ => particle.velocity.y -= PARTICLE_GRAVITY,
     };
 
@@ -105,14 +140,14 @@ fn apply_velocity(
                 _ => {
                     if s > 0 {
                         // Turn into cell
-                        world.set_cell_external(
+                        world.set_cell(
                             transform.translation.truncate().as_ivec2(),
-                            Cell::from(particle.clone()),
+                            Cell::from(*particle),
                         );
                         return true;
                     } else {
                         // Extra velocity in order to get out of whatever area we are in
-                        particle.velocity.y = if matches!(particle.physics, PhysicsType::Gas(_)) {
+                        particle.velocity.y = if matches!(particle.physics, PhysicsType::Gas) {
                             -1.
                         } else {
                             1.
@@ -1,14 +1,20 @@
 use bevy::prelude::*;
 
-use crate::pixel::cell::{Cell, PhysicsType};
+use crate::pixel::{
+    cell::{Cell, CellVelocity, PhysicsType, Temperature},
+    material::MaterialId,
+};
 
 pub const PARTICLE_GRAVITY: f32 = 0.1;
 
-// A particle type with a color and physics based on a cell type, as well as a velocity
+// A particle type with a shade and physics based on a cell's material, as well as a velocity
 #[derive(Component, Clone, Copy, Debug)]
 pub struct Particle {
-    pub color: [u8; 4],
+    pub material: MaterialId,
+    pub shade: u8,
     pub physics: PhysicsType,
+    pub temperature: Temperature,
+    pub lifetime: u16,
 
     pub velocity: Vec2,
 }
@@ -16,18 +22,43 @@ pub struct Particle {
 impl From<Cell> for Particle {
     fn from(value: Cell) -> Self {
         Self {
-            color: value.color,
+            material: value.material,
+            shade: value.shade,
             physics: value.physics,
+            temperature: value.temperature,
+            lifetime: value.lifetime,
             velocity: Vec2::ZERO,
         }
     }
 }
 
+// Turns a particle back into a cell once it comes to rest
+impl From<Particle> for Cell {
+    fn from(value: Particle) -> Self {
+        Self {
+            material: value.material,
+            shade: value.shade,
+            physics: value.physics,
+            temperature: value.temperature,
+            lifetime: value.lifetime,
+            // Keep the momentum of the particle
+            velocity: CellVelocity::new(
+                value.velocity.x.round() as i8,
+                value.velocity.y.round() as i8,
+            ),
+            updated: false,
+        }
+    }
+}
+
 impl Particle {
     pub fn from_cell_with_velocity_position(cell: &Cell, velocity: Vec2) -> Self {
         Self {
-            color: cell.color,
+            material: cell.material,
+            shade: cell.shade,
             physics: cell.physics,
+            temperature: cell.temperature,
+            lifetime: cell.lifetime,
             velocity,
         }
     }
@@ -11,6 +11,7 @@ use crate::input::InteractionInformation;
 use crate::states::{AppSet, DebugState};
 
 use super::cell::Cell;
+use super::material::MaterialRegistry;
 use super::world::PixelWorld;
 
 // Debug information to be stored for the pixel world
@@ -27,7 +28,9 @@ struct PixelSimulationDebug {
     // Amount of chunks
     pub chunk_amount: u32,
     // Size of chunks
-    pub chunk_size: u32,
+    pub chunk_size: UVec2,
+    // Seed of the simulation
+    pub seed: u64,
 
     pub show_chunk_borders: bool,
 }
@@ -65,8 +68,9 @@ fn pixel_simulation_debug(
     dbg.hovered_cell = world.get_cell(cell_pos);
     dbg.inside_dirty_rect = world.cell_inside_dirty(cell_pos);
 
-    dbg.chunk_size = world.get_chunk_width() as u32;
+    dbg.chunk_size = world.chunk_size;
     dbg.chunk_amount = world.get_chunks().len() as u32;
+    dbg.seed = world.seed;
 }
 
 fn pixel_simulation_debug_ui(
@@ -74,6 +78,7 @@ fn pixel_simulation_debug_ui(
     mut dbg: ResMut<PixelSimulationDebug>,
     mut dbg_ui: ResMut<PixelSimulationDebugUi>,
     int: Res<InteractionInformation>,
+    materials: Res<MaterialRegistry>,
 ) {
     egui::Window::new("Debug")
         .open(&mut dbg_ui.show)
@@ -81,6 +86,13 @@ fn pixel_simulation_debug_ui(
             ui.set_min_width(200.);
             ui.label(format!("Current Chunk: {:?}", dbg.chunk_position));
             ui.label(format!("Current Cell: {:?}", dbg.hovered_cell));
+            if let Some(cell) = dbg.hovered_cell {
+                let material = materials.get(cell.material);
+                ui.label(format!(
+                    "Current Material: {} (density {})",
+                    material.name, material.density
+                ));
+            }
             ui.label(format!("Inside dirty rect?: {:?}", dbg.inside_dirty_rect));
             ui.label(format!(
                 "Cell position in world: {:?}",
@@ -95,6 +107,7 @@ fn pixel_simulation_debug_ui(
                 "Amount of chunks/chunk size: {:?}/{:?}",
                 dbg.chunk_amount, dbg.chunk_size
             ));
+            ui.label(format!("Seed: {}", dbg.seed));
             ui.checkbox(&mut dbg.show_chunk_borders, "F2: Toggle chunk overlay, gray outline for chunks,\ngreen outline for dirty rectangles");
             ui.label("F3: Toggle Rapier Physics Engine Debug Overlay");
         });
@@ -114,15 +127,19 @@ pub fn draw_chunk_gizmos(mut chunk_gizmos: Gizmos<ChunkGizmos>, sim: Query<&Pixe
 
     let awake_chunks = world.get_chunk_dirty_rects();
 
-    for (pos, rect) in awake_chunks {
+    for (pos, mut rect) in awake_chunks {
+        // The last chunks of worlds that do not divide evenly are only outlined up to the end of the world
+        let extent = world.chunk_extent(pos);
+        rect.max = rect.max.min(extent.as_ivec2() - IVec2::ONE);
+
         // Calculate position in screen
         let pos = (pos.as_vec2() * world.chunk_size.as_vec2()) - world.world_size.as_vec2() / 2.;
 
         // Draw light gray outline of chunk
         chunk_gizmos.rect_2d(
-            origin + pos + (world.chunk_size.as_vec2() / 2.),
+            origin + pos + (extent.as_vec2() / 2.),
             0.0,
-            world.chunk_size.as_vec2(),
+            extent.as_vec2(),
             LIGHT_GRAY,
         );
         // Draw green outline of dirty rect if exists
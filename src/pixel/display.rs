@@ -10,12 +10,17 @@ use bevy::{
 
 use crate::{screen::Screen, SpawnWorlds};
 
-use super::{world::PixelWorld, LoadedChunks};
+use super::{material::MaterialRegistry, world::PixelWorld, LoadedChunks};
 
 pub(super) fn plugin(app: &mut App) {
     app.add_systems(
         FixedPostUpdate,
-        (create_chunk_displays, update_chunk_displays).run_if(in_state(Screen::Playing)),
+        (
+            remove_chunk_displays,
+            create_chunk_displays,
+            update_chunk_displays,
+        )
+            .run_if(in_state(Screen::Playing)),
     );
 }
 
@@ -27,6 +32,7 @@ struct ChunkDisplayComponent {
 
 // Creates the chunk textures for each chunk
 // Can do in runtime (so that we can load/unload chunks later)
+// The last chunks of worlds that do not divide evenly reach past the end of the world, the cells there stay empty and are drawn transparent
 fn create_chunk_displays(
     mut commands: Commands,
     mut images: ResMut<Assets<Image>>,
@@ -36,8 +42,8 @@ fn create_chunk_displays(
     let pxl_sim = &pxl_sim.single();
 
     // Find all chunks that do not have an image and create one
-    for (pos, _chunk) in &pxl_sim.chunks {
-        if !loaded.chunks.contains(pos) {
+    for pos in pxl_sim.chunks.positions() {
+        if !loaded.chunks.contains_key(&pos) {
             let image = Image::new(
                 Extent3d {
                     width: pxl_sim.get_chunk_width(),
@@ -49,7 +55,7 @@ fn create_chunk_displays(
                 TextureFormat::Rgba8UnormSrgb,
                 RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
             );
-            commands.spawn((
+            let display = commands.spawn((
                 SpriteBundle {
                     texture: images.add(image),
                     transform: Transform::from_translation(
@@ -61,37 +67,58 @@ fn create_chunk_displays(
                     },
                     ..default()
                 },
-                ChunkDisplayComponent { chunk: *pos },
+                ChunkDisplayComponent { chunk: pos },
                 StateScoped(Screen::Playing),
                 RenderLayers::layer(2),
             ));
-            loaded.chunks.push(*pos);
+            loaded.chunks.insert(pos, display.id());
         }
     }
 }
 
+// Removes the display images of chunks which were unloaded
+fn remove_chunk_displays(
+    mut commands: Commands,
+    pxl_sim: Query<&PixelWorld>,
+    mut loaded: ResMut<LoadedChunks>,
+) {
+    let pxl_sim = &pxl_sim.single();
+
+    loaded.chunks.retain(|pos, display| {
+        let keep = pxl_sim.chunks.contains(*pos);
+        if !keep {
+            commands.entity(*display).despawn();
+        }
+        keep
+    });
+}
+
 // Updates all chunk displays if they have updated
+// Colors are looked up from the materials here, the cells themselves only store their material and shade
 fn update_chunk_displays(
     pxl_sim: Query<&PixelWorld>,
     mut chunks_display: Query<(&ChunkDisplayComponent, &mut Handle<Image>)>,
     mut images: ResMut<Assets<Image>>,
+    materials: Res<MaterialRegistry>,
 ) {
     let pxl_sim = &pxl_sim.single();
 
     for (chunk_display, handle) in chunks_display.iter_mut() {
-        if let Some(data) = pxl_sim.should_render_data(chunk_display.chunk) {
+        if pxl_sim.should_render(chunk_display.chunk) {
             let current = images.get_mut(&handle.clone()).unwrap();
-            current.data = data;
+            pxl_sim.render_chunk(chunk_display.chunk, &mut current.data, &materials);
         }
     }
 }
 
 // Create a gradient background to be displayed behind the world
+// It is attached to the camera, so it stays behind the view in streaming worlds where the camera moves
 pub fn setup_gradient_background(
     commands: &mut Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     config: &SpawnWorlds,
+    camera: Entity,
 ) {
     // Build a default quad mesh
     let mut mesh = Mesh::from(Rectangle::default());
@@ -111,10 +138,14 @@ pub fn setup_gradient_background(
     commands
         .spawn(MaterialMesh2dBundle {
             mesh: mesh_handle.clone(),
-            transform: Transform::from_translation(Vec3::new(0., 0., 0.))
-                .with_scale((config.world_size.as_vec2() * 4.).extend(0.)),
+            // Placed at the world origin relative to the camera's starting position
+            transform: Transform::from_translation(
+                (config.world_size.as_vec2() / -2.).extend(-1000.),
+            )
+            .with_scale((config.world_size.as_vec2() * 4.).extend(0.)),
             material: materials.add(ColorMaterial::default()),
             ..default()
         })
-        .insert(StateScoped(Screen::Playing));
+        .insert(StateScoped(Screen::Playing))
+        .set_parent(camera);
 }
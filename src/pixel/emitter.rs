@@ -0,0 +1,141 @@
+//! Emitters and drains which add or remove cells of an area every update, for faucets, waterfalls, smoke stacks and sinks
+//! They are entities, so they can be spawned from gameplay code as well as placed with the brush
+
+use bevy::{prelude::*, render::view::RenderLayers};
+use rand::Rng;
+
+use crate::{
+    screen::Screen,
+    simulation::{simulation_running, SimulationControl},
+};
+
+use super::{
+    cell::{Cell, PhysicsType},
+    material::{MaterialId, MaterialRegistry},
+    update_pixel_simulation,
+    world::PixelWorld,
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        (emit_cells, drain_cells)
+            .before(update_pixel_simulation)
+            .run_if(in_state(Screen::Playing).and_then(simulation_running)),
+    );
+}
+
+// Places cells of a material into random empty cells of its area
+#[derive(Component, Clone, Copy, Debug)]
+pub struct CellEmitter {
+    pub material: MaterialId,
+    // Cells emitted each update, the fraction is emitted by chance
+    pub rate: f32,
+    // World cells the emitter places into, both corners are included
+    pub area: IRect,
+}
+
+// Removes all movable cells inside of its area
+#[derive(Component, Clone, Copy, Debug)]
+pub struct CellDrain {
+    // World cells the drain removes, both corners are included
+    pub area: IRect,
+}
+
+// Spawns an emitter with a marker showing its area in the color of its material
+pub fn spawn_emitter(
+    commands: &mut Commands,
+    emitter: CellEmitter,
+    materials: &MaterialRegistry,
+) -> Entity {
+    let [r, g, b, _] = materials.get(emitter.material).color;
+    commands
+        .spawn((
+            emitter,
+            area_marker(emitter.area, Color::srgba_u8(r, g, b, 100)),
+        ))
+        .id()
+}
+
+// Spawns a drain with a dark marker showing its area
+pub fn spawn_drain(commands: &mut Commands, drain: CellDrain) -> Entity {
+    commands
+        .spawn((
+            drain,
+            area_marker(drain.area, Color::srgba(0., 0., 0., 0.4)),
+        ))
+        .id()
+}
+
+fn area_marker(area: IRect, color: Color) -> impl Bundle {
+    (
+        SpriteBundle {
+            sprite: Sprite {
+                color,
+                custom_size: Some((area.size() + IVec2::ONE).as_vec2()),
+                ..default()
+            },
+            // Cells are drawn from their bottom left corner, so the center of the area is offset by half a cell
+            transform: Transform::from_translation(
+                ((area.min + area.max).as_vec2() / 2. + 0.5).extend(3.),
+            ),
+            ..default()
+        },
+        RenderLayers::layer(2),
+        StateScoped(Screen::Playing),
+    )
+}
+
+fn emit_cells(
+    emitters: Query<&CellEmitter>,
+    mut sim: Query<&mut PixelWorld>,
+    materials: Res<MaterialRegistry>,
+    control: Res<SimulationControl>,
+) {
+    let world = &mut sim.single_mut();
+    for emitter in emitters.iter() {
+        // The rate is per simulation step, so sped up simulations emit more per update
+        let rate = emitter.rate * control.steps() as f32;
+        let mut amount = rate as u32;
+        if world.rng().gen::<f32>() < rate.fract() {
+            amount += 1;
+        }
+        for _ in 0..amount {
+            let position = IVec2 {
+                x: world
+                    .rng()
+                    .gen_range(emitter.area.min.x..=emitter.area.max.x),
+                y: world
+                    .rng()
+                    .gen_range(emitter.area.min.y..=emitter.area.max.y),
+            };
+            if world.get_cell(position).is_some_and(|cell| cell.is_empty()) {
+                world.set_material(position, emitter.material, &materials);
+            }
+        }
+    }
+}
+
+fn drain_cells(drains: Query<&CellDrain>, mut sim: Query<&mut PixelWorld>) {
+    let world = &mut sim.single_mut();
+
+    for drain in drains.iter() {
+        for y in drain.area.min.y..=drain.area.max.y {
+            for x in drain.area.min.x..=drain.area.max.x {
+                let position = IVec2 { x, y };
+                // Solid and rigid body cells are left alone, only cells that could flow in are drained
+                if world.get_cell(position).is_some_and(|cell| {
+                    matches!(
+                        cell.physics,
+                        PhysicsType::SoftSolid
+                            | PhysicsType::Liquid
+                            | PhysicsType::Gas
+                            | PhysicsType::Fire
+                    )
+                }) {
+                    world.set_cell(position, Cell::default());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! Explosions which blast cells out of the pixel world
+//! An `Explosion` event can be sent from gameplay code, the brush tool sends one on click and explosive materials queue one when they catch fire
+//! Movable cells inside of the blast are thrown as particles, rigid bodies are pushed away by the rigid plugin
+
+use bevy::prelude::*;
+
+pub use sandengine_core::explosion::Explosion;
+
+use crate::{particles::spawn_particle, screen::Screen};
+
+use super::{
+    history::EditHistory, material::MaterialRegistry, update_pixel_simulation, world::PixelWorld,
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_event::<Explosion>().add_systems(
+        FixedUpdate,
+        (queue_material_explosions, apply_explosions)
+            .chain()
+            .after(update_pixel_simulation)
+            // Explosions set off while paused go off right away, in the update their input was dispatched in
+            .run_if(in_state(Screen::Playing)),
+    );
+}
+
+// Sends the explosions of explosive materials which caught fire during the last update
+fn queue_material_explosions(
+    mut sim: Query<&mut PixelWorld>,
+    mut explosions: EventWriter<Explosion>,
+) {
+    explosions.send_batch(sim.single_mut().take_explosions());
+}
+
+// Applies explosions to the pixel world, throwing the blasted cells as particles
+// Explosions set off by the player are recorded so they can be undone
+pub fn apply_explosions(
+    mut commands: Commands,
+    mut explosions: EventReader<Explosion>,
+    mut sim: Query<&mut PixelWorld>,
+    mut history: ResMut<EditHistory>,
+    materials: Res<MaterialRegistry>,
+) {
+    let world = &mut sim.single_mut();
+    for explosion in explosions.read() {
+        let blasted = if explosion.undoable {
+            history.explode(world, explosion, &materials)
+        } else {
+            world.explode(explosion, &materials)
+        };
+        for blasted in blasted {
+            spawn_particle(
+                &mut commands,
+                &blasted.cell,
+                &materials,
+                blasted.velocity,
+                blasted.position,
+            );
+        }
+    }
+}
@@ -1,65 +1,171 @@
 //! Interaction with pixel world
 
-use bevy::prelude::*;
+use bevy::{prelude::*, window::PrimaryWindow};
 
 use bevy::math::IVec2;
 use bevy_egui::{egui, EguiContexts};
-use strum::{IntoEnumIterator, VariantNames};
+use strum::{EnumIter, IntoEnumIterator, VariantNames};
 
 use crate::input::InteractionInformation;
+use crate::replay::{WorldInput, WorldInputQueue};
 use crate::screen::Screen;
+use crate::simulation::{SimulationControl, MAX_SIMULATION_SPEED};
+#[cfg(not(target_family = "wasm"))]
+use crate::{
+    replay::SaveRecording,
+    save::{LoadWorld, SaveWorld},
+};
 
-use super::cell::{Cell, CellType};
+use super::emitter::{spawn_drain, spawn_emitter, CellDrain, CellEmitter};
+use super::explosion::Explosion;
+use super::history::EditHistory;
+use super::material::{MaterialFlag, MaterialId, MaterialRegistry};
 use super::world::PixelWorld;
-use super::GameCamera;
+use super::{update_pixel_simulation, GameCamera};
+
+// Force of explosions set off with the brush
+const BRUSH_EXPLOSION_FORCE: f32 = 20.;
 
 // Information about interacting with the pixel world
 #[derive(Resource)]
 pub struct PixelInteraction {
-    // Type of cell to be placed on click
-    pub place_cell_type: CellType,
+    // What left click does
+    pub tool: PixelTool,
+    // Material of the cells to be placed on click
+    pub place_material: MaterialId,
     // Amount of cell to place
     pub place_cell_amount: i32,
+    // Cells emitted each update by placed emitters
+    pub emitter_rate: f32,
 }
 
 impl Default for PixelInteraction {
     fn default() -> Self {
         Self {
+            tool: PixelTool::default(),
             place_cell_amount: 8,
-            place_cell_type: CellType::Sand,
+            // Picked once the materials are loaded
+            place_material: MaterialId::EMPTY,
+            emitter_rate: 2.,
         }
     }
 }
 
+#[derive(Debug, Default, EnumIter, VariantNames, PartialEq, Eq, Clone, Copy)]
+pub enum PixelTool {
+    #[default]
+    Place,
+    // Sets off an explosion the size of the brush
+    Explode,
+    // Places an emitter of the selected material the size of the brush
+    Emitter,
+    // Places a drain the size of the brush
+    Drain,
+}
+
 pub(super) fn plugin(app: &mut App) {
-    app.init_resource::<PixelInteraction>();
+    app.init_resource::<PixelInteraction>()
+        .init_resource::<EditHistory>();
     app.add_systems(
         Update,
-        (pixel_interaction_config, handle_mouse_input, touch_events)
+        (
+            pixel_interaction_config.run_if(any_with_component::<PrimaryWindow>),
+            handle_mouse_input,
+            touch_events,
+            undo_redo_edits,
+        )
+            .run_if(in_state(Screen::Playing)),
+    )
+    // Edits are made while paused as well
+    .add_systems(
+        FixedUpdate,
+        apply_pixel_inputs
+            .before(update_pixel_simulation)
             .run_if(in_state(Screen::Playing)),
     );
 }
 
-fn pixel_interaction_config(mut ctx: EguiContexts, mut pxl: ResMut<PixelInteraction>) {
+fn pixel_interaction_config(
+    mut ctx: EguiContexts,
+    mut pxl: ResMut<PixelInteraction>,
+    mut control: ResMut<SimulationControl>,
+    history: Res<EditHistory>,
+    mut queue: ResMut<WorldInputQueue>,
+    materials: Res<MaterialRegistry>,
+    #[cfg(not(target_family = "wasm"))] mut save: EventWriter<SaveWorld>,
+    #[cfg(not(target_family = "wasm"))] mut load: EventWriter<LoadWorld>,
+    #[cfg(not(target_family = "wasm"))] mut save_recording: EventWriter<SaveRecording>,
+) {
     egui::Window::new("Pixel Simulation Controls").show(ctx.ctx_mut(), |ui| {
         ui.horizontal(|ui| {
             ui.group(|ui| {
                 ui.vertical(|ui| {
                     ui.label("Controls:");
-                    ui.label("Left click: Place selected cell material.");
-                    ui.label("Left Control + Left click: Erase cell material.");
+                    ui.label("Left click: Use selected tool.");
+                    ui.label("Left Control + Left click: Erase cell material, or remove emitters and drains.");
+                    ui.label("Left Control + Z / Y: Undo or redo edits.");
+
+                    for (tool, name) in PixelTool::iter().zip(PixelTool::VARIANTS.iter()) {
+                        ui.radio_value(&mut pxl.tool, tool, *name);
+                    }
 
                     ui.label("Size of cell placement brush:");
                     ui.add(egui::Slider::new(&mut pxl.place_cell_amount, 8..=80));
+                    ui.label("Cells emitted per update:");
+                    ui.add(egui::Slider::new(&mut pxl.emitter_rate, 0.1..=20.));
                     ui.label("Press F1 to toggle debug window.");
+
+                    ui.label("Simulation speed:");
+                    ui.add(egui::Slider::new(&mut control.speed, 0.25..=MAX_SIMULATION_SPEED));
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(history.can_undo(), egui::Button::new("Undo"))
+                            .clicked()
+                        {
+                            queue.push(WorldInput::Undo);
+                        }
+                        if ui
+                            .add_enabled(history.can_redo(), egui::Button::new("Redo"))
+                            .clicked()
+                        {
+                            queue.push(WorldInput::Redo);
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut control.paused, "Paused (P)");
+                        if ui
+                            .add_enabled(control.paused, egui::Button::new("Step (.)"))
+                            .clicked()
+                        {
+                            control.step();
+                        }
+                    });
+
+                    // Saves are written to the working directory, which the web build does not have
+                    #[cfg(not(target_family = "wasm"))]
+                    ui.horizontal(|ui| {
+                        if ui.button("Save").clicked() {
+                            save.send(SaveWorld);
+                        }
+                        if ui.button("Load").clicked() {
+                            load.send(LoadWorld);
+                        }
+                        if ui.button("Save Recording").clicked() {
+                            save_recording.send(SaveRecording);
+                        }
+                    });
                 });
             });
 
             ui.group(|ui| {
                 ui.set_min_width(60.);
                 ui.vertical(|ui| {
-                    for (cell_type, name) in CellType::iter().zip(CellType::VARIANTS.iter()) {
-                        ui.radio_value(&mut pxl.place_cell_type, cell_type, *name);
+                    for (id, material) in materials.iter() {
+                        if material.flags.contains(MaterialFlag::Hidden) {
+                            continue;
+                        }
+                        ui.radio_value(&mut pxl.place_material, id, &material.name);
                     }
                 });
             });
@@ -67,8 +173,15 @@ fn pixel_interaction_config(mut ctx: EguiContexts, mut pxl: ResMut<PixelInteract
     });
 }
 
-// Intended to be called with cell type
-fn place_cells(world: &mut PixelWorld, position: IVec2, amount: i32, cell_type: CellType) {
+// Intended to be called with a material, the cells are recorded in the current edit of the history
+fn place_cells(
+    world: &mut PixelWorld,
+    history: &mut EditHistory,
+    position: IVec2,
+    amount: i32,
+    material: MaterialId,
+    materials: &MaterialRegistry,
+) {
     let amt_to_place_quarter = amount / 4;
     let amt_to_place_half = amount / 2;
     for x in -amt_to_place_half..=amt_to_place_half {
@@ -77,7 +190,45 @@ fn place_cells(world: &mut PixelWorld, position: IVec2, amount: i32, cell_type:
             if (x * x) + (y * y) > amt_to_place_quarter * amt_to_place_quarter {
                 continue;
             }
-            world.set_cell_external(position + IVec2 { x, y }, Cell::from(cell_type));
+            history.set_material(world, position + IVec2 { x, y }, material, materials);
+        }
+    }
+}
+
+// Input placing an emitter or drain covering the brush
+fn place_cell_source(pxl: &PixelInteraction, position: IVec2) -> Option<WorldInput> {
+    let area = IRect::from_center_size(position, IVec2::splat(pxl.place_cell_amount / 2));
+    match pxl.tool {
+        PixelTool::Emitter => Some(WorldInput::PlaceEmitter(CellEmitter {
+            material: pxl.place_material,
+            rate: pxl.emitter_rate,
+            area,
+        })),
+        PixelTool::Drain => Some(WorldInput::PlaceDrain(CellDrain { area })),
+        _ => None,
+    }
+}
+
+// Input setting off an explosion the size of the brush
+fn brush_explosion(pxl: &PixelInteraction, position: IVec2) -> WorldInput {
+    WorldInput::Explode(Explosion {
+        center: position,
+        radius: pxl.place_cell_amount as f32 / 2.,
+        force: BRUSH_EXPLOSION_FORCE,
+        undoable: true,
+    })
+}
+
+// Removes all emitters and drains covering the position
+fn remove_cell_sources(
+    commands: &mut Commands,
+    sources: &Query<(Entity, AnyOf<(&CellEmitter, &CellDrain)>)>,
+    position: IVec2,
+) {
+    for (entity, (emitter, drain)) in sources.iter() {
+        let area = emitter.map_or_else(|| drain.unwrap().area, |emitter| emitter.area);
+        if area.contains(position) {
+            commands.entity(entity).despawn_recursive();
         }
     }
 }
@@ -85,59 +236,147 @@ fn place_cells(world: &mut PixelWorld, position: IVec2, amount: i32, cell_type:
 fn handle_mouse_input(
     mouse_buttons: Res<ButtonInput<MouseButton>>,
     keyboard_buttons: Res<ButtonInput<KeyCode>>,
-    mut sim: Query<&mut PixelWorld>,
-    pxl: ResMut<PixelInteraction>,
+    mut queue: ResMut<WorldInputQueue>,
+    pxl: Res<PixelInteraction>,
     int: Res<InteractionInformation>,
 ) {
+    // A brush stroke is one edit, from pressing the button until releasing it
+    if mouse_buttons.just_released(MouseButton::Left) {
+        queue.push(WorldInput::FinishStroke);
+    }
+
     // Don't do anything if we are hovering over UI
     if int.hovering_ui {
         return;
     }
 
-    let world = &mut sim.single_mut();
+    let position = int.mouse_position.as_ivec2();
 
     if mouse_buttons.pressed(MouseButton::Left) {
-        // Delete cells if control is held
-        if keyboard_buttons.pressed(KeyCode::ControlLeft) {
-            place_cells(
-                world,
-                int.mouse_position.as_ivec2(),
-                pxl.place_cell_amount,
-                CellType::Empty,
-            );
+        if matches!(pxl.tool, PixelTool::Emitter | PixelTool::Drain) {
+            // Placed or removed once per click
+            if mouse_buttons.just_pressed(MouseButton::Left) {
+                if keyboard_buttons.pressed(KeyCode::ControlLeft) {
+                    queue.push(WorldInput::RemoveCellSources(position));
+                } else if let Some(input) = place_cell_source(&pxl, position) {
+                    queue.push(input);
+                }
+            }
+        } else if keyboard_buttons.pressed(KeyCode::ControlLeft) {
+            // Delete cells if control is held
+            queue.push(WorldInput::Brush {
+                position,
+                amount: pxl.place_cell_amount,
+                material: MaterialId::EMPTY,
+            });
+        } else if pxl.tool == PixelTool::Explode {
+            // One explosion per click
+            if mouse_buttons.just_pressed(MouseButton::Left) {
+                queue.push(brush_explosion(&pxl, position));
+            }
         } else {
-            place_cells(
-                world,
-                int.mouse_position.as_ivec2(),
-                pxl.place_cell_amount,
-                pxl.place_cell_type,
-            );
+            queue.push(WorldInput::Brush {
+                position,
+                amount: pxl.place_cell_amount,
+                material: pxl.place_material,
+            });
         }
     }
 }
 
 fn touch_events(
     mut touch_evr: EventReader<TouchInput>,
-    mut sim: Query<&mut PixelWorld>,
-    pxl: ResMut<PixelInteraction>,
+    mut queue: ResMut<WorldInputQueue>,
+    pxl: Res<PixelInteraction>,
     camera: Query<(&Camera, &GlobalTransform), With<GameCamera>>,
 ) {
     use bevy::input::touch::TouchPhase;
-    let world = &mut sim.single_mut();
 
     for ev in touch_evr.read() {
         match ev.phase {
             TouchPhase::Started | TouchPhase::Moved => {
                 let (cam, trans) = camera.single();
-                if let Some(position) = cam.viewport_to_world_2d(trans, ev.position) {
-                    place_cells(
-                        world,
-                        position.as_ivec2(),
-                        pxl.place_cell_amount,
-                        pxl.place_cell_type,
-                    );
+                let Some(position) = cam.viewport_to_world_2d(trans, ev.position) else {
+                    continue;
+                };
+                let position = position.as_ivec2();
+                match pxl.tool {
+                    PixelTool::Place => queue.push(WorldInput::Brush {
+                        position,
+                        amount: pxl.place_cell_amount,
+                        material: pxl.place_material,
+                    }),
+                    PixelTool::Explode if ev.phase == TouchPhase::Started => {
+                        queue.push(brush_explosion(&pxl, position));
+                    }
+                    PixelTool::Emitter | PixelTool::Drain if ev.phase == TouchPhase::Started => {
+                        if let Some(input) = place_cell_source(&pxl, position) {
+                            queue.push(input);
+                        }
+                    }
+                    _ => {}
                 }
             }
+            TouchPhase::Ended | TouchPhase::Canceled => queue.push(WorldInput::FinishStroke),
+        }
+    }
+}
+
+// Undoes edits with Control + Z and redoes them with Control + Y or Control + Shift + Z
+fn undo_redo_edits(
+    keyboard_buttons: Res<ButtonInput<KeyCode>>,
+    mut queue: ResMut<WorldInputQueue>,
+) {
+    if !keyboard_buttons.pressed(KeyCode::ControlLeft) {
+        return;
+    }
+    let shift = keyboard_buttons.pressed(KeyCode::ShiftLeft);
+    if keyboard_buttons.just_pressed(KeyCode::KeyY)
+        || (shift && keyboard_buttons.just_pressed(KeyCode::KeyZ))
+    {
+        queue.push(WorldInput::Redo);
+    } else if keyboard_buttons.just_pressed(KeyCode::KeyZ) {
+        queue.push(WorldInput::Undo);
+    }
+}
+
+// Applies the inputs dispatched this update which change the pixel world
+fn apply_pixel_inputs(
+    mut commands: Commands,
+    mut inputs: EventReader<WorldInput>,
+    mut sim: Query<&mut PixelWorld>,
+    mut history: ResMut<EditHistory>,
+    materials: Res<MaterialRegistry>,
+    mut explosions: EventWriter<Explosion>,
+    sources: Query<(Entity, AnyOf<(&CellEmitter, &CellDrain)>)>,
+) {
+    let world = &mut sim.single_mut();
+    for input in inputs.read() {
+        match *input {
+            WorldInput::Brush {
+                position,
+                amount,
+                material,
+            } => place_cells(world, &mut history, position, amount, material, &materials),
+            WorldInput::FinishStroke => history.commit(),
+            WorldInput::Explode(explosion) => {
+                explosions.send(explosion);
+            }
+            WorldInput::PlaceEmitter(emitter) => {
+                spawn_emitter(&mut commands, emitter, &materials);
+            }
+            WorldInput::PlaceDrain(drain) => {
+                spawn_drain(&mut commands, drain);
+            }
+            WorldInput::RemoveCellSources(position) => {
+                remove_cell_sources(&mut commands, &sources, position)
+            }
+            WorldInput::Undo => {
+                history.undo(world);
+            }
+            WorldInput::Redo => {
+                history.redo(world);
+            }
             _ => {}
         }
     }
@@ -0,0 +1,139 @@
+//! Loading of the materials used by the pixel simulation
+//! The definitions are loaded as an asset, the registry resource is rebuilt whenever they are loaded or hot reloaded
+//! Cells only store the id of their material, so everything holding ids is remapped by name when the registry is rebuilt
+
+use bevy::prelude::*;
+
+pub use sandengine_core::material::*;
+
+use crate::{replay::Recording, SpawnWorlds};
+
+use super::{
+    chunk_generator, emitter::CellEmitter, history::EditHistory, interaction::PixelInteraction,
+    world::PixelWorld,
+};
+
+// Path of the material definitions loaded at startup
+const MATERIALS_PATH: &str = "materials/default.materials.ron";
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_asset::<MaterialDefinitions>()
+        .register_asset_loader(MaterialDefinitionsLoader)
+        .init_resource::<MaterialRegistry>()
+        .add_event::<MaterialsReloaded>()
+        .add_systems(Startup, load_material_definitions)
+        .configure_sets(Update, RemapMaterials.after(update_material_registry))
+        .add_systems(
+            Update,
+            (
+                update_material_registry,
+                remap_pixel_materials.in_set(RemapMaterials),
+            ),
+        );
+}
+
+// Systems which remap material ids after a reload, they run after the registry is rebuilt in the same frame
+#[derive(SystemSet, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct RemapMaterials;
+
+// Sent when the registry is rebuilt, with the table from the ids of the old registry to the new ones
+// Anything that holds material ids should remap them with `MaterialId::remap` or `Cell::remap_material`
+#[derive(Event)]
+pub struct MaterialsReloaded {
+    pub remap: Vec<MaterialId>,
+}
+
+// Handle to keep the material definitions loaded
+#[derive(Resource)]
+pub struct MaterialDefinitionsHandle {
+    pub handle: Handle<MaterialDefinitions>,
+}
+
+fn load_material_definitions(mut commands: Commands, server: Res<AssetServer>) {
+    commands.insert_resource(MaterialDefinitionsHandle {
+        handle: server.load(MATERIALS_PATH),
+    });
+}
+
+// Rebuild the registry whenever the definitions are loaded or hot reloaded
+// Materials may be added, removed or reordered while playing, ids are matched up by name
+fn update_material_registry(
+    mut events: EventReader<AssetEvent<MaterialDefinitions>>,
+    definitions: Res<Assets<MaterialDefinitions>>,
+    handle: Option<Res<MaterialDefinitionsHandle>>,
+    mut registry: ResMut<MaterialRegistry>,
+    mut reloaded: EventWriter<MaterialsReloaded>,
+) {
+    let Some(handle) = handle else {
+        return;
+    };
+    for event in events.read() {
+        match event {
+            AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id }
+                if *id == handle.handle.id() =>
+            {
+                if let Some(definitions) = definitions.get(*id) {
+                    let new = MaterialRegistry::from_definitions(definitions);
+                    reloaded.send(MaterialsReloaded {
+                        remap: new.remap_from(&registry),
+                    });
+                    *registry = new;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Remaps the material ids held by the pixel world, its edit history, emitters and the placement controls
+fn remap_pixel_materials(
+    mut commands: Commands,
+    mut events: EventReader<MaterialsReloaded>,
+    registry: Res<MaterialRegistry>,
+    config: Option<Res<SpawnWorlds>>,
+    mut worlds: Query<&mut PixelWorld>,
+    mut history: ResMut<EditHistory>,
+    mut interaction: ResMut<PixelInteraction>,
+    mut emitters: Query<(Entity, &mut CellEmitter)>,
+    recording: Option<ResMut<Recording>>,
+) {
+    let mut reloaded = false;
+    let mut changed_while_playing = false;
+    for MaterialsReloaded { remap } in events.read() {
+        reloaded = true;
+        // The first load remaps from the registry which only holds the empty material
+        changed_while_playing |= remap.len() > 1;
+        for mut world in worlds.iter_mut() {
+            world.remap_materials(remap, &registry);
+        }
+        history.remap_materials(remap, &registry);
+        interaction.place_material = interaction.place_material.remap(remap);
+        for (entity, mut emitter) in emitters.iter_mut() {
+            emitter.material = emitter.material.remap(remap);
+            if emitter.material == MaterialId::EMPTY {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+    if !reloaded {
+        return;
+    }
+
+    // The generator looks up the materials it places when it is created
+    if let Some(config) = config {
+        for mut world in worlds.iter_mut() {
+            world.set_generator(chunk_generator(&config, &registry));
+        }
+    }
+    // Also picks the material placed at first, once the definitions are loaded
+    if interaction.place_material == MaterialId::EMPTY {
+        interaction.place_material = registry
+            .id("Sand")
+            .or_else(|| registry.iter().nth(1).map(|(id, _)| id))
+            .unwrap_or(MaterialId::EMPTY);
+    }
+    // Replays start with the materials as they are now, they can't reproduce them changing while recording
+    if let (true, Some(mut recording)) = (changed_while_playing, recording) {
+        recording.stop();
+    }
+}
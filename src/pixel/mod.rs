@@ -1,23 +1,36 @@
 //! Pixel module managing the pixel/cell world that runs on a cellular-automata like system
-//! `world.rs` manages this behavior
+//! The simulation itself lives in the `sandengine_core` crate, this plugin runs it and layers rendering and input on top
 //! The plugin also manages input/debug windows for managing the pixel world and spawns the main game camera
 
-pub mod cell;
-mod chunk;
-mod chunk_handler;
 pub mod debug;
 mod display;
-mod geometry_helpers;
+pub mod emitter;
+pub mod explosion;
 pub mod interaction;
-pub mod world;
+pub mod material;
+pub mod streaming;
+
+pub use sandengine_core::{cell, generation, history, islands, world};
 
 use bevy::{
     prelude::*,
     render::{camera::ScalingMode, view::RenderLayers},
+    utils::HashMap,
 };
 use display::setup_gradient_background;
+use streaming::{ChunkLoader, ChunkStreaming};
 
-use crate::{pixel::world::PixelWorld, screen::Screen, SpawnWorlds};
+use crate::{
+    pixel::{
+        generation::{ChunkGenerator, EmptyGenerator, TerrainGenerator},
+        history::EditHistory,
+        material::MaterialRegistry,
+        world::PixelWorld,
+    },
+    screen::Screen,
+    simulation::{simulation_running, SimulationControl},
+    SpawnWorlds,
+};
 
 pub struct PixelPlugin;
 
@@ -26,18 +39,26 @@ impl Plugin for PixelPlugin {
         app.insert_resource(LoadedChunks::default())
             .add_systems(
                 FixedUpdate,
-                update_pixel_simulation.run_if(in_state(Screen::Playing)),
+                update_pixel_simulation
+                    .run_if(in_state(Screen::Playing).and_then(simulation_running)),
             )
-            .add_plugins((display::plugin, interaction::plugin));
+            .add_plugins((
+                display::plugin,
+                emitter::plugin,
+                explosion::plugin,
+                interaction::plugin,
+                material::plugin,
+                streaming::plugin,
+            ));
 
         app.add_plugins(debug::plugin);
     }
 }
 
-// Resource which defines which chunks are loaded. Currently only used to know which chunks have an image for display
+// Resource which tracks the display image entity of each loaded chunk, so they can be removed when their chunk is unloaded
 #[derive(Resource, Default)]
 pub(crate) struct LoadedChunks {
-    pub chunks: Vec<IVec2>,
+    pub chunks: HashMap<IVec2, Entity>,
 }
 
 #[derive(Component)]
@@ -49,9 +70,12 @@ pub fn spawn_pixel_world(
     mut commands: Commands,
     meshes: ResMut<Assets<Mesh>>,
     materials: ResMut<Assets<ColorMaterial>>,
+    registry: Res<MaterialRegistry>,
     mut loaded_chunks: ResMut<LoadedChunks>,
+    mut streaming: ResMut<ChunkStreaming>,
+    mut history: ResMut<EditHistory>,
 ) {
-    commands
+    let camera = commands
         .spawn(Camera2dBundle {
             projection: OrthographicProjection {
                 scaling_mode: ScalingMode::AutoMin {
@@ -75,19 +99,54 @@ pub fn spawn_pixel_world(
             // Layers: 0 (default), 1 (rigidbodies), 2 (cells/pixels), 3 (particles)
             RenderLayers::from_layers(&[0, 1, 2, 3]),
             GameCamera,
-        ));
+            // Loads enough chunks to fill the view, even when the window is much wider than it is tall
+            ChunkLoader {
+                radius: config.chunk_amount.max_element() as i32,
+            },
+        ))
+        .id();
 
-    let world = PixelWorld::new(config.world_size, config.chunk_amount);
+    let mut world = PixelWorld::with_generator(
+        config.world_size,
+        config.chunk_amount,
+        config.seed,
+        chunk_generator(&config, &registry),
+    );
+    // Streaming worlds load chunks outside of the starting area
+    if config.streaming {
+        world.chunks.make_sparse();
+    }
 
     commands.spawn(world).insert(StateScoped(Screen::Playing));
 
-    setup_gradient_background(&mut commands, meshes, materials, &config);
+    setup_gradient_background(&mut commands, meshes, materials, &config, camera);
 
-    // Reset loaded chunks
+    // Reset loaded chunks and the edits of the previous world
     loaded_chunks.chunks.clear();
+    history.clear();
+    streaming.enabled = config.streaming;
 }
 
-// Update the pixel world
-pub fn update_pixel_simulation(mut query: Query<&mut PixelWorld>) {
-    query.single_mut().update();
+// Generator for the new chunks of worlds spawned with the config
+pub(crate) fn chunk_generator(
+    config: &SpawnWorlds,
+    registry: &MaterialRegistry,
+) -> Box<dyn ChunkGenerator> {
+    // Terrain starts a third of the way up, leaving room above it to build
+    match TerrainGenerator::new(registry, config.world_size.y as i32 / 3) {
+        Some(terrain) if config.terrain => Box::new(terrain),
+        _ => Box::new(EmptyGenerator),
+    }
+}
+
+// Update the pixel world, as many steps as the simulation control asks for
+pub fn update_pixel_simulation(
+    mut query: Query<&mut PixelWorld>,
+    materials: Res<MaterialRegistry>,
+    control: Res<SimulationControl>,
+) {
+    let world = &mut query.single_mut();
+    for _ in 0..control.steps() {
+        world.step(&materials);
+    }
 }
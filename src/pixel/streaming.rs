@@ -0,0 +1,90 @@
+//! Streams chunks in and out of the pixel world around entities with a `ChunkLoader`, such as the camera and the player
+//! Only worlds spawned as streaming worlds do this, fixed size worlds keep all of their chunks
+//! Chunks far away from every loader are stored and dropped by the pixel world, their displays and colliders are removed along with them
+//! The pixel world only stores so many chunks, chunks far away from where the loaders left are generated again when they come back
+
+use bevy::prelude::*;
+
+use crate::screen::Screen;
+
+use super::{update_pixel_simulation, world::PixelWorld};
+
+pub(super) fn plugin(app: &mut App) {
+    app.insert_resource(ChunkStreaming::default()).add_systems(
+        FixedUpdate,
+        stream_chunks
+            .before(update_pixel_simulation)
+            .run_if(in_state(Screen::Playing).and_then(streaming_enabled)),
+    );
+}
+
+// Whether chunks are streamed, set when the worlds are spawned
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct ChunkStreaming {
+    pub enabled: bool,
+    // Chunks are unloaded once they are this many chunks outside of the radius of every loader
+    // This keeps chunks on the border of a radius from being loaded and unloaded over and over
+    pub unload_margin: i32,
+}
+
+impl Default for ChunkStreaming {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            unload_margin: 2,
+        }
+    }
+}
+
+pub fn streaming_enabled(streaming: Res<ChunkStreaming>) -> bool {
+    streaming.enabled
+}
+
+// Keeps the chunks around the entity loaded in streaming worlds
+#[derive(Component, Clone, Copy, Debug)]
+pub struct ChunkLoader {
+    // Radius in chunks around the chunk the entity is in
+    pub radius: i32,
+}
+
+// Loads the chunks in the radius of each loader and unloads the chunks far away from all of them
+fn stream_chunks(
+    loaders: Query<(&ChunkLoader, &Transform)>,
+    mut sim: Query<&mut PixelWorld>,
+    streaming: Res<ChunkStreaming>,
+) {
+    let world = &mut sim.single_mut();
+
+    let loaders: Vec<(IVec2, i32)> = loaders
+        .iter()
+        .map(|(loader, transform)| {
+            // Loaders are not parented, the transform is read as it is right now rather than after the last propagation
+            let position = transform.translation.truncate().floor().as_ivec2();
+            (
+                PixelWorld::cell_to_chunk_position(world.chunk_size, position),
+                loader.radius,
+            )
+        })
+        .collect();
+
+    for (center, radius) in &loaders {
+        for y in -radius..=*radius {
+            for x in -radius..=*radius {
+                world.load_chunk(*center + IVec2 { x, y });
+            }
+        }
+    }
+
+    let far: Vec<IVec2> = world
+        .chunks
+        .positions()
+        .filter(|position| {
+            loaders.iter().all(|(center, radius)| {
+                (*position - *center).abs().max_element() > radius + streaming.unload_margin
+            })
+        })
+        .collect();
+    for position in far {
+        world.unload_chunk(position);
+    }
+}
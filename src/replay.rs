@@ -0,0 +1,503 @@
+//! Recording of the inputs which change the world, and playback of recordings to reproduce a session exactly
+//! Inputs such as brush strokes, placed bodies and the player's controls are queued as `WorldInput`s instead of being applied right away
+//! At the start of every fixed update the queued inputs are stamped with the number of the update, recorded, and sent on to the plugins which apply them
+//! A recording holds the settings and seed of the worlds along with the inputs, so playing it back spawns the same worlds and changes them the same way
+//! Recordings can be played back from the title screen, or without a window with `--replay <file> --headless`
+
+use std::{collections::VecDeque, path::PathBuf};
+
+use bevy::prelude::*;
+
+use sandengine_core::save::{FileFormat, SaveError, SaveReader, SaveWriter};
+
+use crate::{
+    pixel::{
+        emitter::{CellDrain, CellEmitter},
+        explosion::Explosion,
+        material::{MaterialId, MaterialRegistry},
+        world::PixelWorld,
+    },
+    rigid::{interaction::PlaceableRigidBodies, Player, PlayerControls},
+    screen::Screen,
+    simulation::{plan_simulation_steps, SimulationControl},
+    spawn_worlds, FixedWorldTerrain, SpawnWorlds, WorldSizes,
+};
+
+use strum::IntoEnumIterator;
+
+// Where the recording of the current session is saved, relative to the working directory
+pub const RECORDING_PATH: &str = "recordings/session.sandrec";
+
+// Recordings share the body of save files but have their own header, so one is never read as the other
+const RECORDING_FORMAT: FileFormat = FileFormat {
+    magic: *b"SREC",
+    version: 1,
+};
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_resource::<WorldInputQueue>()
+        .init_resource::<Recording>()
+        .add_event::<WorldInput>()
+        .add_event::<SaveRecording>()
+        .add_systems(
+            OnEnter(Screen::Title),
+            start_pending_replay.run_if(resource_exists::<PendingReplay>),
+        )
+        .add_systems(
+            OnEnter(Screen::Playing),
+            spawn_replay_worlds.run_if(resource_exists::<PendingReplay>),
+        )
+        .add_systems(OnExit(Screen::Playing), stop_replay)
+        .add_systems(
+            FixedFirst,
+            dispatch_inputs
+                .before(plan_simulation_steps)
+                .run_if(in_state(Screen::Playing)),
+        )
+        .add_systems(Update, save_recording.run_if(in_state(Screen::Playing)));
+}
+
+// An input which changes the world, applied in the fixed update it is dispatched in
+#[derive(Event, Clone, Copy, Debug)]
+pub enum WorldInput {
+    // Places a round brush of a material, empty cells erase
+    Brush {
+        position: IVec2,
+        amount: i32,
+        material: MaterialId,
+    },
+    // Ends the current brush stroke, so it is undone as one edit
+    FinishStroke,
+    Explode(Explosion),
+    PlaceEmitter(CellEmitter),
+    PlaceDrain(CellDrain),
+    // Removes the emitters and drains covering the position
+    RemoveCellSources(IVec2),
+    Undo,
+    Redo,
+    // Bodies are placed ten at a time when batched
+    PlaceRigidBody {
+        position: IVec2,
+        body: PlaceableRigidBodies,
+        batch: bool,
+    },
+    PlaceDynamicEntity {
+        position: Vec2,
+        batch: bool,
+    },
+    // Controls of the player from this update on
+    Player(PlayerControls),
+    // Pause and speed of the simulation from this update on
+    Control {
+        paused: bool,
+        speed: f32,
+    },
+    // Runs a single step of the paused simulation
+    Step,
+}
+
+// Inputs made since the last fixed update, dispatched at the start of the next one
+#[derive(Resource, Default)]
+pub struct WorldInputQueue {
+    inputs: Vec<WorldInput>,
+}
+
+impl WorldInputQueue {
+    pub fn push(&mut self, input: WorldInput) {
+        self.inputs.push(input);
+    }
+}
+
+// Saves the recording of the current session
+#[derive(Event)]
+pub struct SaveRecording;
+
+// Inputs dispatched since the worlds were spawned, a new recording is started along with every new world
+#[derive(Resource, Default)]
+pub struct Recording {
+    // Settings of the recorded worlds, None if the session can not be recorded such as after loading a save
+    config: Option<SpawnWorlds>,
+    // Fixed updates since the worlds were spawned
+    tick: u32,
+    inputs: Vec<(u32, WorldInput)>,
+    // Pause and speed of the simulation as last dispatched, only changes are recorded
+    control: (bool, f32),
+}
+
+impl Recording {
+    pub fn new(config: SpawnWorlds) -> Self {
+        Self {
+            config: Some(config),
+            tick: 0,
+            inputs: Vec::new(),
+            control: (false, 1.),
+        }
+    }
+
+    // Stops recording, once the world has changed in a way that can not be played back
+    pub fn stop(&mut self) {
+        if self.config.take().is_some() {
+            warn!("Recording stopped, the session can no longer be played back");
+        }
+    }
+}
+
+// Recording to play back once the game has loaded
+#[derive(Resource)]
+pub struct PendingReplay {
+    pub path: PathBuf,
+    // Quits the app once the replay is finished, used when running without a window
+    pub exit_when_finished: bool,
+}
+
+// Recording which is being played back, live input is ignored until it is finished
+#[derive(Resource)]
+struct Replay {
+    inputs: VecDeque<(u32, WorldInput)>,
+    // Number of fixed updates in the recording
+    end: u32,
+    exit_when_finished: bool,
+}
+
+// The recording is read once the worlds are spawned, as inputs refer to materials by name
+fn start_pending_replay(mut next_screen: ResMut<NextState<Screen>>) {
+    next_screen.set(Screen::Loading);
+}
+
+fn spawn_replay_worlds(
+    mut commands: Commands,
+    pending: Res<PendingReplay>,
+    materials: Res<MaterialRegistry>,
+    world_size: Res<State<WorldSizes>>,
+    terrain: Res<FixedWorldTerrain>,
+    mut exit: EventWriter<AppExit>,
+) {
+    commands.remove_resource::<PendingReplay>();
+
+    let replay = std::fs::read(&pending.path)
+        .map_err(SaveError::from)
+        .and_then(|bytes| read_recording(&bytes, &materials));
+    match replay {
+        Ok((config, mut replay)) => {
+            info!(
+                "Playing back {} inputs over {} updates from {}",
+                replay.inputs.len(),
+                replay.end,
+                pending.path.display()
+            );
+            replay.exit_when_finished = pending.exit_when_finished;
+            commands.insert_resource(replay);
+            commands.add(config);
+        }
+        Err(err) => {
+            error!("Could not play back {}: {err}", pending.path.display());
+            if pending.exit_when_finished {
+                exit.send(AppExit::error());
+            } else {
+                spawn_worlds(&mut commands, world_size, terrain);
+            }
+        }
+    }
+}
+
+fn stop_replay(mut commands: Commands) {
+    commands.remove_resource::<Replay>();
+}
+
+// Stamps the inputs of this fixed update, records them and sends them on, or takes them from the replay instead
+fn dispatch_inputs(
+    mut commands: Commands,
+    mut queue: ResMut<WorldInputQueue>,
+    mut recording: ResMut<Recording>,
+    mut replay: Option<ResMut<Replay>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut player: ResMut<PlayerControls>,
+    mut control: ResMut<SimulationControl>,
+    mut inputs: EventWriter<WorldInput>,
+    sim: Query<&PixelWorld>,
+    player_transform: Query<&Transform, With<Player>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let tick = recording.tick;
+    recording.tick += 1;
+
+    let mut dispatched = Vec::new();
+    match replay.as_deref_mut() {
+        Some(replay) => {
+            if tick >= replay.end {
+                // Compared against the log of the recorded session, to check that it was reproduced
+                let position = player_transform
+                    .get_single()
+                    .map(|transform| transform.translation.truncate())
+                    .unwrap_or_default();
+                info!(
+                    "Replay finished after {tick} updates, world checksum {:016x}, player at {position}",
+                    sim.single().checksum()
+                );
+                commands.remove_resource::<Replay>();
+                if replay.exit_when_finished {
+                    exit.send(AppExit::Success);
+                }
+                return;
+            }
+
+            // Live input would change what happens, the simulation control is held at the recorded values
+            queue.inputs.clear();
+            (control.paused, control.speed) = recording.control;
+            control.step_requested = false;
+            while replay.inputs.front().is_some_and(|(at, _)| *at == tick) {
+                dispatched.extend(replay.inputs.pop_front().map(|(_, input)| input));
+            }
+        }
+        None => {
+            dispatched.append(&mut queue.inputs);
+            let live = PlayerControls::from_keyboard(&keyboard);
+            if live != *player {
+                dispatched.push(WorldInput::Player(live));
+            }
+            if (control.paused, control.speed) != recording.control {
+                dispatched.push(WorldInput::Control {
+                    paused: control.paused,
+                    speed: control.speed,
+                });
+            }
+            // Steps only run while paused, otherwise the request is dropped
+            if control.step_requested && control.paused {
+                dispatched.push(WorldInput::Step);
+            }
+        }
+    }
+
+    for input in dispatched {
+        match input {
+            WorldInput::Player(controls) => *player = controls,
+            WorldInput::Control { paused, speed } => {
+                (control.paused, control.speed) = (paused, speed);
+                recording.control = (paused, speed);
+            }
+            WorldInput::Step => control.step(),
+            _ => {
+                inputs.send(input);
+            }
+        }
+        if recording.config.is_some() {
+            recording.inputs.push((tick, input));
+        }
+    }
+}
+
+fn save_recording(
+    mut requests: EventReader<SaveRecording>,
+    recording: Res<Recording>,
+    materials: Res<MaterialRegistry>,
+    sim: Query<&PixelWorld>,
+) {
+    if requests.read().count() == 0 {
+        return;
+    }
+    let Some(config) = recording.config else {
+        error!("Nothing to save, this session is not being recorded");
+        return;
+    };
+
+    let result = write_recording(&config, &recording, &materials).and_then(|bytes| {
+        std::fs::create_dir_all("recordings")?;
+        std::fs::write(RECORDING_PATH, bytes)?;
+        Ok(())
+    });
+    match result {
+        Ok(()) => info!(
+            "Saved recording of {} updates to {RECORDING_PATH}, world checksum {:016x}",
+            recording.tick,
+            sim.single().checksum()
+        ),
+        Err(err) => error!("Could not save recording to {RECORDING_PATH}: {err}"),
+    }
+}
+
+fn write_recording(
+    config: &SpawnWorlds,
+    recording: &Recording,
+    materials: &MaterialRegistry,
+) -> Result<Vec<u8>, SaveError> {
+    let mut writer = SaveWriter::with_format(RECORDING_FORMAT, materials);
+    writer.write_uvec2(config.world_size);
+    writer.write_uvec2(config.chunk_amount);
+    writer.write_u64(config.seed);
+    writer.write_bool(config.streaming);
+    writer.write_bool(config.terrain);
+
+    writer.write_u32(recording.tick);
+    writer.write_u32(recording.inputs.len() as u32);
+    for (tick, input) in &recording.inputs {
+        writer.write_u32(*tick);
+        write_input(&mut writer, input);
+    }
+    writer.finish()
+}
+
+fn read_recording(
+    bytes: &[u8],
+    materials: &MaterialRegistry,
+) -> Result<(SpawnWorlds, Replay), SaveError> {
+    let mut reader = SaveReader::with_format(bytes, RECORDING_FORMAT, materials)?;
+    let config = SpawnWorlds {
+        world_size: reader.read_uvec2()?,
+        chunk_amount: reader.read_uvec2()?,
+        seed: reader.read_u64()?,
+        streaming: reader.read_bool()?,
+        terrain: reader.read_bool()?,
+    };
+    // Chunks are only at least two cells wide and high, as worlds require, when there are fewer of them than cells
+    if config.chunk_amount.min_element() == 0 || config.world_size.cmple(config.chunk_amount).any()
+    {
+        return Err(SaveError::Corrupt);
+    }
+
+    let end = reader.read_u32()?;
+    let mut inputs = VecDeque::new();
+    let mut previous = 0;
+    for _ in 0..reader.read_u32()? {
+        let tick = reader.read_u32()?;
+        // Inputs are dispatched in order, a recording going back in time has been tampered with
+        if tick < previous || tick >= end {
+            return Err(SaveError::Corrupt);
+        }
+        previous = tick;
+        inputs.push_back((tick, read_input(&mut reader)?));
+    }
+
+    Ok((
+        config,
+        Replay {
+            inputs,
+            end,
+            exit_when_finished: false,
+        },
+    ))
+}
+
+fn write_input(writer: &mut SaveWriter, input: &WorldInput) {
+    match *input {
+        WorldInput::Brush {
+            position,
+            amount,
+            material,
+        } => {
+            writer.write_u8(0);
+            writer.write_ivec2(position);
+            writer.write_i32(amount);
+            writer.write_material(material);
+        }
+        WorldInput::FinishStroke => writer.write_u8(1),
+        WorldInput::Explode(explosion) => {
+            writer.write_u8(2);
+            writer.write_ivec2(explosion.center);
+            writer.write_f32(explosion.radius);
+            writer.write_f32(explosion.force);
+            writer.write_bool(explosion.undoable);
+        }
+        WorldInput::PlaceEmitter(emitter) => {
+            writer.write_u8(3);
+            writer.write_material(emitter.material);
+            writer.write_f32(emitter.rate);
+            writer.write_ivec2(emitter.area.min);
+            writer.write_ivec2(emitter.area.max);
+        }
+        WorldInput::PlaceDrain(drain) => {
+            writer.write_u8(4);
+            writer.write_ivec2(drain.area.min);
+            writer.write_ivec2(drain.area.max);
+        }
+        WorldInput::RemoveCellSources(position) => {
+            writer.write_u8(5);
+            writer.write_ivec2(position);
+        }
+        WorldInput::Undo => writer.write_u8(6),
+        WorldInput::Redo => writer.write_u8(7),
+        WorldInput::PlaceRigidBody {
+            position,
+            body,
+            batch,
+        } => {
+            writer.write_u8(8);
+            writer.write_ivec2(position);
+            writer.write_u8(body as u8);
+            writer.write_bool(batch);
+        }
+        WorldInput::PlaceDynamicEntity { position, batch } => {
+            writer.write_u8(9);
+            writer.write_vec2(position);
+            writer.write_bool(batch);
+        }
+        WorldInput::Player(controls) => {
+            writer.write_u8(10);
+            writer.write_u8(
+                controls.left as u8
+                    | (controls.right as u8) << 1
+                    | (controls.jump as u8) << 2
+                    | (controls.dash as u8) << 3,
+            );
+        }
+        WorldInput::Control { paused, speed } => {
+            writer.write_u8(11);
+            writer.write_bool(paused);
+            writer.write_f32(speed);
+        }
+        WorldInput::Step => writer.write_u8(12),
+    }
+}
+
+fn read_input(reader: &mut SaveReader) -> Result<WorldInput, SaveError> {
+    Ok(match reader.read_u8()? {
+        0 => WorldInput::Brush {
+            position: reader.read_ivec2()?,
+            amount: reader.read_i32()?,
+            material: reader.read_material()?,
+        },
+        1 => WorldInput::FinishStroke,
+        2 => WorldInput::Explode(Explosion {
+            center: reader.read_ivec2()?,
+            radius: reader.read_f32()?,
+            force: reader.read_f32()?,
+            undoable: reader.read_bool()?,
+        }),
+        3 => WorldInput::PlaceEmitter(CellEmitter {
+            material: reader.read_material()?,
+            rate: reader.read_f32()?,
+            area: IRect::from_corners(reader.read_ivec2()?, reader.read_ivec2()?),
+        }),
+        4 => WorldInput::PlaceDrain(CellDrain {
+            area: IRect::from_corners(reader.read_ivec2()?, reader.read_ivec2()?),
+        }),
+        5 => WorldInput::RemoveCellSources(reader.read_ivec2()?),
+        6 => WorldInput::Undo,
+        7 => WorldInput::Redo,
+        8 => WorldInput::PlaceRigidBody {
+            position: reader.read_ivec2()?,
+            body: PlaceableRigidBodies::iter()
+                .nth(reader.read_u8()? as usize)
+                .ok_or(SaveError::Corrupt)?,
+            batch: reader.read_bool()?,
+        },
+        9 => WorldInput::PlaceDynamicEntity {
+            position: reader.read_vec2()?,
+            batch: reader.read_bool()?,
+        },
+        10 => {
+            let bits = reader.read_u8()?;
+            WorldInput::Player(PlayerControls {
+                left: bits & 1 != 0,
+                right: bits & 2 != 0,
+                jump: bits & 4 != 0,
+                dash: bits & 8 != 0,
+            })
+        }
+        11 => WorldInput::Control {
+            paused: reader.read_bool()?,
+            speed: reader.read_f32()?,
+        },
+        12 => WorldInput::Step,
+        _ => return Err(SaveError::Corrupt),
+    })
+}
@@ -4,8 +4,29 @@ use bevy_tnua::control_helpers::TnuaSimpleAirActionsCounter;
 use bevy_tnua::math::{Float, Vector3};
 use bevy_tnua::prelude::*;
 
+// Controls of the player for the current fixed update
+// They are dispatched as a world input, so that recordings can play them back
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PlayerControls {
+    pub left: bool,
+    pub right: bool,
+    pub jump: bool,
+    pub dash: bool,
+}
+
+impl PlayerControls {
+    pub fn from_keyboard(keyboard: &ButtonInput<KeyCode>) -> Self {
+        Self {
+            left: keyboard.any_pressed([KeyCode::ArrowLeft, KeyCode::KeyA]),
+            right: keyboard.any_pressed([KeyCode::ArrowRight, KeyCode::KeyD]),
+            jump: keyboard.any_pressed([KeyCode::Space, KeyCode::ArrowUp, KeyCode::KeyW]),
+            dash: keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]),
+        }
+    }
+}
+
 pub fn apply_platformer_controls(
-    keyboard: Res<ButtonInput<KeyCode>>,
+    controls: Res<PlayerControls>,
     mut query: Query<(
         &CharacterMotionConfigForPlatformer,
         // This is the main component used for interacting with Tnua. It is used for both issuing
@@ -23,21 +44,19 @@ pub fn apply_platformer_controls(
     // Get query results
     let (config, mut controller, mut air_actions_counter) = query.single_mut();
 
-    // This part is just keyboard input processing. In a real game this would probably be done
-    // with a third party plugin.
     let mut direction = Vector3::ZERO;
 
-    if keyboard.any_pressed([KeyCode::ArrowLeft, KeyCode::KeyA]) {
+    if controls.left {
         direction -= Vector3::X;
     }
-    if keyboard.any_pressed([KeyCode::ArrowRight, KeyCode::KeyD]) {
+    if controls.right {
         direction += Vector3::X;
     }
 
     direction = direction.clamp_length_max(1.0);
 
-    let jump = { keyboard.any_pressed([KeyCode::Space, KeyCode::ArrowUp, KeyCode::KeyW]) };
-    let dash = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let jump = controls.jump;
+    let dash = controls.dash;
 
     // This needs to be called once per frame. It lets the air actions counter know about the
     // air status of the character. Specifically:
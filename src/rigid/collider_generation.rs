@@ -12,6 +12,7 @@ use super::RigidStorage;
 // Generates colliders for the chunks in the pixel simulation
 // This function will regenerate a collider for each chunk in the simulation and add it to the rigid storage
 // If the chunk's dirty rectangle has not changed since the last frame, it will not generate a new collider
+// Colliders of chunks which were unloaded are removed, including those whose index was given to another chunk
 // Chunk collider generate uses a polyline collider created through a simplified marching squares algorithm
 pub fn chunk_collider_generation(
     pixel_sim: Query<&mut PixelWorld>,
@@ -23,25 +24,35 @@ pub fn chunk_collider_generation(
     let chunk_width = world.get_chunk_width();
     let chunk_height = world.get_chunk_height();
 
-    // Make sure collider storage initialized with correct amount
-    if rigid_storage.colliders.len() as u32 != world.chunk_amount.x * world.chunk_amount.y {
-        rigid_storage
-            .colliders
-            .resize((world.chunk_amount.x * world.chunk_amount.y) as usize, None);
+    // Grids only grow while a world is loaded, slots past the end of a smaller grid have no chunk and are cleared below
+    let index_bound = world.chunks.index_bound();
+    if rigid_storage.colliders.len() < index_bound {
+        rigid_storage.colliders.resize_with(index_bound, || None);
+    }
+    for (index, slot) in rigid_storage.colliders.iter_mut().enumerate() {
+        let loaded = slot.as_ref().is_some_and(|(position, _)| {
+            world
+                .chunks
+                .by_index(index)
+                .is_some_and(|chunk| chunk.position == *position)
+        });
+        if !loaded {
+            if let Some((_, entities)) = slot.take() {
+                for e in entities {
+                    commands.entity(e).despawn();
+                }
+            }
+        }
     }
 
-    let chunks = world
-        .get_chunks()
-        .into_iter()
-        .enumerate()
-        .map(|(i, chunk)| (i, chunk))
-        .collect::<Vec<_>>();
-
-    let (tx, rx) = channel::<(usize, Option<Vec<Collider>>)>();
+    let (tx, rx) = channel::<(usize, IVec2, Option<Vec<Collider>>)>();
 
     let mut update_counter = 0;
     ComputeTaskPool::get().scope(|scope| {
-        for (index, chunk) in chunks {
+        for index in 0..index_bound {
+            let Some(chunk) = world.chunks.by_index(index) else {
+                continue;
+            };
             if !chunk.should_update() {
                 continue;
             }
@@ -73,33 +84,30 @@ pub fn chunk_collider_generation(
                     for collider in colliders {
                         id.push(collider);
                     }
-                    tx.send((index, Some(id))).unwrap();
+                    tx.send((index, chunk.position, Some(id))).unwrap();
                 } else {
-                    tx.send((index, None)).unwrap();
+                    tx.send((index, chunk.position, None)).unwrap();
                 }
             });
         }
     });
 
     for _ in 0..update_counter {
-        let (idx, colliders) = rx.recv().unwrap();
+        let (index, position, colliders) = rx.recv().unwrap();
         // Despawn existing colliders
-        if let Some(entities) = &rigid_storage.colliders[idx] {
+        if let Some((_, entities)) = rigid_storage.colliders[index].take() {
             for e in entities {
-                commands.entity(*e).despawn();
+                commands.entity(e).despawn();
             }
         }
         // Place new colliders in by mapping to new entities
-        match colliders {
-            None => rigid_storage.colliders[idx] = None,
-            Some(colliders) => {
-                // map to entities
-                let entities: Vec<Entity> = colliders
-                    .into_iter()
-                    .map(|c| commands.spawn((c, StateScoped(Screen::Playing))).id())
-                    .collect();
-                rigid_storage.colliders[idx] = Some(entities)
-            }
+        if let Some(colliders) = colliders {
+            // map to entities
+            let entities: Vec<Entity> = colliders
+                .into_iter()
+                .map(|c| commands.spawn((c, StateScoped(Screen::Playing))).id())
+                .collect();
+            rigid_storage.colliders[index] = Some((position, entities));
         }
     }
 }
@@ -1,11 +1,21 @@
 /// Type of rigid bodies that interact with the sand simulation
-use bevy::{prelude::*, render::view::RenderLayers, sprite::Anchor};
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        view::RenderLayers,
+    },
+    sprite::Anchor,
+};
 use bevy_rapier2d::prelude::{Collider, ReadMassProperties, Restitution, RigidBody, Velocity};
 
 use crate::{
     particles::spawn_particle,
     pixel::{
-        cell::{Cell, CellType, PhysicsType},
+        cell::{Cell, PhysicsType},
+        islands::CellIsland,
+        material::{MaterialId, MaterialRegistry, MaterialsReloaded},
         world::PixelWorld,
     },
     screen::Screen,
@@ -38,28 +48,102 @@ impl DynamicPhysicsEntity {
         position: Vec2,
         image: &Image,
         handle: Handle<Image>,
-        cell_type: CellType,
+        material: MaterialId,
+    ) -> Option<Self> {
+        if let Some((pc, collider)) = process_image(image, material) {
+            return Some(Self::from_parts(position, pc, collider, handle, false));
+        }
+        None
+    }
+
+    /// Creates an entity out of an island of cells that was cut out of the pixel world
+    fn from_island(
+        island: &CellIsland,
+        images: &mut Assets<Image>,
+        materials: &MaterialRegistry,
     ) -> Option<Self> {
-        if let Some((pc, collider)) = process_image(image, cell_type) {
-            return Some(Self {
-                collider,
-                rigidbody: RigidBody::Dynamic,
-                mass: ReadMassProperties::default(),
-                restitution: Restitution::coefficient(0.5),
-                velocity: Velocity::default(),
-                pixel: pc,
-                sprite: SpriteBundle {
-                    texture: handle,
-                    sprite: Sprite {
-                        anchor: Anchor::BottomLeft,
-                        ..Default::default()
-                    },
-                    transform: Transform::from_translation(position.extend(1.)),
+        // The cells are stored bottom row first, so the image is flipped when rendering like the chunk displays
+        Self::from_cells(
+            island.position.as_vec2(),
+            island.size,
+            &island.cells,
+            true,
+            images,
+            materials,
+        )
+    }
+
+    /// Creates an entity out of cells, such as those of a saved entity, with an image made from their colors
+    pub fn from_cells(
+        position: Vec2,
+        size: UVec2,
+        cells: &[Cell],
+        flip_y: bool,
+        images: &mut Assets<Image>,
+        materials: &MaterialRegistry,
+    ) -> Option<Self> {
+        let values: Vec<f64> = cells
+            .iter()
+            .map(|cell| if cell.is_empty() { 0.0 } else { 1.0 })
+            .collect();
+        let collider =
+            create_convex_collider_from_values(values.as_slice(), size.x as f32, size.y as f32)?;
+
+        let image = Image::new(
+            Extent3d {
+                width: size.x,
+                height: size.y,
+                ..default()
+            },
+            TextureDimension::D2,
+            cells
+                .iter()
+                .flat_map(|cell| {
+                    if cell.is_empty() {
+                        [0; 4]
+                    } else {
+                        materials.cell_color(cell)
+                    }
+                })
+                .collect(),
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        );
+
+        Some(Self::from_parts(
+            position,
+            PixelComponent::from_cells(size, cells),
+            collider,
+            images.add(image),
+            flip_y,
+        ))
+    }
+
+    fn from_parts(
+        position: Vec2,
+        pixel: PixelComponent,
+        collider: Collider,
+        handle: Handle<Image>,
+        flip_y: bool,
+    ) -> Self {
+        Self {
+            collider,
+            rigidbody: RigidBody::Dynamic,
+            mass: ReadMassProperties::default(),
+            restitution: Restitution::coefficient(0.5),
+            velocity: Velocity::default(),
+            pixel,
+            sprite: SpriteBundle {
+                texture: handle,
+                sprite: Sprite {
+                    anchor: Anchor::BottomLeft,
+                    flip_y,
                     ..Default::default()
                 },
-            });
+                transform: Transform::from_translation(position.extend(1.)),
+                ..Default::default()
+            },
         }
-        None
     }
 }
 
@@ -69,11 +153,15 @@ pub fn add_dpe(
     images: &Res<Assets<Image>>,
     position: Vec2,
     rigidbody_image: &Res<RigidBodyImageHandle>,
+    materials: &MaterialRegistry,
 ) {
     let image_handle = rigidbody_image.handle.clone().unwrap();
     let image = images.get(&image_handle).unwrap();
+    let Some(stone) = materials.id("Stone") else {
+        return;
+    };
 
-    let dpe = DynamicPhysicsEntity::new(position, image, image_handle.clone(), CellType::Stone);
+    let dpe = DynamicPhysicsEntity::new(position, image, image_handle.clone(), stone);
     if let Some(dpe) = dpe {
         commands
             .spawn(dpe)
@@ -92,14 +180,34 @@ pub struct PixelComponent {
 }
 
 impl PixelComponent {
-    /// Creates a pixel component from an image with the given cell type for all cells
-    pub fn from_image(image: &Image, cell_type: CellType) -> Self {
+    /// Creates a pixel component from an image with the given material for all cells
+    /// The entity is drawn with the image, the shades of the cells only vary with its colors
+    pub fn from_image(image: &Image, material: MaterialId) -> Self {
         let size = image.size();
         let cells: Vec<Cell> = image
             .data
             .chunks_exact(4)
             .into_iter()
-            .map(|p| Cell::with_cell_and_color_rigidbody(cell_type, [p[0], p[1], p[2], 255]))
+            .map(|p| Cell::with_material_and_shade_rigidbody(material, p[0] ^ p[1] ^ p[2]))
+            .collect();
+        PixelComponent {
+            size,
+            cells,
+            filled_tracker: Vec::new(),
+        }
+    }
+
+    /// Creates a pixel component from cells of the pixel world, empty cells are left out of the component
+    pub fn from_cells(size: UVec2, cells: &[Cell]) -> Self {
+        let cells = cells
+            .iter()
+            .map(|cell| {
+                if cell.is_empty() {
+                    Cell::default()
+                } else {
+                    Cell::with_material_and_shade_rigidbody(cell.material, cell.shade)
+                }
+            })
             .collect();
         PixelComponent {
             size,
@@ -124,13 +232,13 @@ fn image_valuemap(image: &Image) -> Vec<f64> {
 }
 
 /// Create the collider and pixel component of an image
-fn process_image(image: &Image, cell_type: CellType) -> Option<(PixelComponent, Collider)> {
+fn process_image(image: &Image, material: MaterialId) -> Option<(PixelComponent, Collider)> {
     if let Some(collider) = create_convex_collider_from_values(
         image_valuemap(image).as_slice(),
         image.width() as f32,
         image.height() as f32,
     ) {
-        return Some((PixelComponent::from_image(image, cell_type), collider));
+        return Some((PixelComponent::from_image(image, material), collider));
     }
     None
 }
@@ -158,6 +266,7 @@ pub fn fill_pixel_component(
         &Velocity,
         &ReadMassProperties,
     )>,
+    materials: Res<MaterialRegistry>,
 ) {
     let world = &mut sim.single_mut();
 
@@ -182,7 +291,7 @@ pub fn fill_pixel_component(
                 let r_cell = pixel.cells[(y * pixel.size.x + x) as usize];
                 // Make sure the physics type is correct (rigidbody)
                 match r_cell.physics {
-                    PhysicsType::RigidBody(_) => {
+                    PhysicsType::RigidBody => {
                         // Update the world cells based on the physics types, converting into particles
 
                         // If the cell will be converted into a particle or otherwise removed from the world or overwritten, set this flag to true
@@ -190,16 +299,11 @@ pub fn fill_pixel_component(
                         // Because the dpe is rendered through image and not the internal pixel simulation, we need to ensure cells will not be overwritten when
                         // only a small amount of the component is inside a cell
                         let mut should_destroy_cell = false;
-                        match PhysicsType::from(
-                            // Get the type of the cell in the world, if it does not exist (dpe may be out of pixel world bounds), treat it as empty
-                            if let Some(cell) = w_cell {
-                                cell.physics
-                            } else {
-                                PhysicsType::Empty
-                            },
-                        ) {
+                        // Get the cell in the world, if it does not exist (dpe may be out of pixel world bounds), treat it as empty
+                        let w_cell = w_cell.unwrap_or_default();
+                        match w_cell.physics {
                             PhysicsType::Empty => should_destroy_cell = true,
-                            PhysicsType::SoftSolid(cell_type) | PhysicsType::Liquid(cell_type) => {
+                            PhysicsType::SoftSolid | PhysicsType::Liquid => {
                                 // Calculate the center of mass and the velocity at that point on the dpe
                                 let center_of_mass =
                                     mass.local_center_of_mass + transform.translation.xy();
@@ -210,7 +314,8 @@ pub fn fill_pixel_component(
 
                                 spawn_particle(
                                     &mut commands,
-                                    &Cell::from(cell_type),
+                                    &w_cell,
+                                    &materials,
                                     normalized_velocity,
                                     pos.as_vec2(),
                                 );
@@ -222,7 +327,7 @@ pub fn fill_pixel_component(
                         // Place the cell in the dpe into the world and keep track
                         if should_destroy_cell {
                             pixel.filled_tracker.push(pos);
-                            world.set_cell_external(pos, Cell::object());
+                            world.set_cell(pos, Cell::object());
                         }
                     }
                     _ => {}
@@ -241,7 +346,65 @@ pub fn unfill_pixel_component(
 
     for mut pixel in &mut dpe {
         while let Some(pos) = pixel.filled_tracker.pop() {
-            world.set_cell_external(pos, Cell::default())
+            world.set_cell(pos, Cell::default())
+        }
+    }
+}
+
+/// Turn islands of solid cells that are no longer connected to the ground into falling dynamic physics entities
+pub fn collapse_floating_islands(
+    mut commands: Commands,
+    mut sim: Query<&mut PixelWorld>,
+    mut images: ResMut<Assets<Image>>,
+    materials: Res<MaterialRegistry>,
+) {
+    let world = &mut sim.single_mut();
+
+    for island in world.take_floating_islands() {
+        // Thin islands can't make a proper collider
+        let dpe = if island.size.x > 1 && island.size.y > 1 {
+            DynamicPhysicsEntity::from_island(&island, &mut images, &materials)
+        } else {
+            None
+        };
+
+        match dpe {
+            Some(dpe) => {
+                commands
+                    .spawn(dpe)
+                    .insert((StateScoped(Screen::Playing), RenderLayers::layer(1)));
+            }
+            None => {
+                // Let the cells fall as particles instead
+                for (i, cell) in island.cells.iter().enumerate() {
+                    if cell.is_empty() {
+                        continue;
+                    }
+                    let offset = UVec2::new(i as u32 % island.size.x, i as u32 / island.size.x);
+                    spawn_particle(
+                        &mut commands,
+                        cell,
+                        &materials,
+                        Vec2::ZERO,
+                        (island.position + offset.as_ivec2()).as_vec2(),
+                    );
+                }
+            }
+        }
+    }
+}
+
+// Moves the cells of dynamic bodies over to the registry when the materials are reloaded
+pub fn remap_pixel_component_materials(
+    mut events: EventReader<MaterialsReloaded>,
+    mut bodies: Query<&mut PixelComponent>,
+    registry: Res<MaterialRegistry>,
+) {
+    for MaterialsReloaded { remap } in events.read() {
+        for mut body in bodies.iter_mut() {
+            for cell in body.cells.iter_mut() {
+                cell.remap_material(remap, &registry);
+            }
         }
     }
 }
@@ -0,0 +1,65 @@
+// Pushes rigid bodies away from explosions in the pixel world
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    pixel::explosion::{apply_explosions, Explosion},
+    screen::Screen,
+};
+
+// Change in velocity of a body at the center of an explosion per unit of force
+const EXPLOSION_SPEED: f32 = 1.5;
+// Smallest share of the force a body overlapping the edge of an explosion still gets
+const MIN_FALLOFF: f32 = 0.2;
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(
+        FixedUpdate,
+        apply_explosion_impulses
+            .after(apply_explosions)
+            .run_if(in_state(Screen::Playing)),
+    );
+}
+
+// Applies an impulse away from the center of each explosion to the dynamic bodies overlapping it
+// The impulse is scaled by the mass of the body so that small and large bodies are thrown alike
+fn apply_explosion_impulses(
+    mut commands: Commands,
+    mut explosions: EventReader<Explosion>,
+    rapier_context: Res<RapierContext>,
+    bodies: Query<(&GlobalTransform, &ReadMassProperties)>,
+) {
+    for explosion in explosions.read() {
+        let center = explosion.center.as_vec2();
+        let shape = Collider::ball(explosion.radius);
+        rapier_context.intersections_with_shape(
+            center,
+            0.,
+            &shape,
+            QueryFilter::only_dynamic(),
+            |entity| {
+                let Ok((transform, mass)) = bodies.get(entity) else {
+                    return true;
+                };
+                let mass = mass.get();
+                let body_center = transform
+                    .transform_point(mass.local_center_of_mass.extend(0.))
+                    .truncate();
+                let offset = body_center - center;
+                let direction = if offset == Vec2::ZERO {
+                    Vec2::Y
+                } else {
+                    offset.normalize()
+                };
+                let falloff = (1. - offset.length() / explosion.radius).max(MIN_FALLOFF);
+
+                commands.entity(entity).insert(ExternalImpulse {
+                    impulse: direction * explosion.force * falloff * mass.mass * EXPLOSION_SPEED,
+                    torque_impulse: 0.,
+                });
+                true
+            },
+        );
+    }
+}
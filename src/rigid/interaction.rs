@@ -1,10 +1,15 @@
 // Interaction with rigid bodies
 
-use bevy::prelude::*;
+use bevy::{prelude::*, window::PrimaryWindow};
 use bevy_egui::{egui, EguiContexts};
 use strum::{EnumIter, IntoEnumIterator, VariantNames};
 
-use crate::{input::InteractionInformation, screen::Screen};
+use crate::{
+    input::InteractionInformation,
+    pixel::material::MaterialRegistry,
+    replay::{WorldInput, WorldInputQueue},
+    screen::Screen,
+};
 
 use super::{
     dynamic_entity::{add_dpe, RigidBodyImageHandle},
@@ -39,7 +44,15 @@ pub(super) fn plugin(app: &mut App) {
     app.init_resource::<RigidInteraction>();
     app.add_systems(
         Update,
-        (rigid_interaction_config, handle_input).run_if(in_state(Screen::Playing)),
+        (
+            rigid_interaction_config.run_if(any_with_component::<PrimaryWindow>),
+            handle_input,
+        )
+            .run_if(in_state(Screen::Playing)),
+    )
+    .add_systems(
+        FixedUpdate,
+        apply_rigid_inputs.run_if(in_state(Screen::Playing)),
     );
 }
 
@@ -65,49 +78,70 @@ fn rigid_interaction_config(mut ctx: EguiContexts, mut rgd: ResMut<RigidInteract
 }
 
 fn handle_input(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
     mouse_button_input: Res<ButtonInput<MouseButton>>,
     keyboard_buttons: Res<ButtonInput<KeyCode>>,
+    mut queue: ResMut<WorldInputQueue>,
     rgd: Res<RigidInteraction>,
     int: Res<InteractionInformation>,
-
-    images: Res<Assets<Image>>,
-    rigidbody_image: Res<RigidBodyImageHandle>,
 ) {
     if !int.hovering_ui && mouse_button_input.just_released(MouseButton::Right) {
+        // Add 10 with shift held
+        let batch = keyboard_buttons.pressed(KeyCode::ShiftLeft);
         // Place DPE with control held
         if keyboard_buttons.pressed(KeyCode::ControlLeft) {
-            if keyboard_buttons.pressed(KeyCode::ShiftLeft) {
-                // Add 10
-                for _ in 0..10 {
+            queue.push(WorldInput::PlaceRigidBody {
+                position: int.mouse_position.as_ivec2(),
+                body: rgd.place_rigid_type,
+                batch,
+            });
+        } else {
+            queue.push(WorldInput::PlaceDynamicEntity {
+                position: int.mouse_position,
+                batch,
+            });
+        }
+    }
+}
+
+// Places the bodies of the inputs dispatched this update
+fn apply_rigid_inputs(
+    mut commands: Commands,
+    mut inputs: EventReader<WorldInput>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    images: Res<Assets<Image>>,
+    rigidbody_image: Res<RigidBodyImageHandle>,
+    registry: Res<MaterialRegistry>,
+) {
+    for input in inputs.read() {
+        match *input {
+            WorldInput::PlaceRigidBody {
+                position,
+                body,
+                batch,
+            } => {
+                for _ in 0..if batch { 10 } else { 1 } {
                     add_non_dynamic_rigidbody(
                         &mut commands,
                         &mut meshes,
                         &mut materials,
-                        int.mouse_position.as_ivec2(),
-                        rgd.place_rigid_type,
+                        position,
+                        body,
                     );
                 }
-            } else {
-                add_non_dynamic_rigidbody(
-                    &mut commands,
-                    &mut meshes,
-                    &mut materials,
-                    int.mouse_position.as_ivec2(),
-                    rgd.place_rigid_type,
-                );
             }
-        } else {
-            if keyboard_buttons.pressed(KeyCode::ShiftLeft) {
-                // Add 10
-                for _ in 0..10 {
-                    add_dpe(&mut commands, &images, int.mouse_position, &rigidbody_image);
+            WorldInput::PlaceDynamicEntity { position, batch } => {
+                for _ in 0..if batch { 10 } else { 1 } {
+                    add_dpe(
+                        &mut commands,
+                        &images,
+                        position,
+                        &rigidbody_image,
+                        &registry,
+                    );
                 }
-            } else {
-                add_dpe(&mut commands, &images, int.mouse_position, &rigidbody_image);
             }
+            _ => {}
         }
     }
 }
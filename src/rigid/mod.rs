@@ -4,9 +4,12 @@
 mod character_control_tnua;
 mod collider_generation;
 pub mod dynamic_entity;
-mod interaction;
+mod explosion;
+pub mod interaction;
 mod rigidbodies;
 
+pub use character_control_tnua::PlayerControls;
+
 use std::f32::consts::FRAC_PI_4;
 
 use bevy::ecs::schedule::ScheduleLabel;
@@ -23,10 +26,20 @@ use bevy_tnua_rapier2d::{TnuaRapier2dIOBundle, TnuaRapier2dPlugin, TnuaRapier2dS
 use character_control_tnua::{apply_platformer_controls, CharacterMotionConfigForPlatformer};
 use collider_generation::chunk_collider_generation;
 use dynamic_entity::{
-    fill_pixel_component, load_rigidbody_image, unfill_pixel_component, RigidBodyImageHandle,
+    collapse_floating_islands, fill_pixel_component, load_rigidbody_image,
+    remap_pixel_component_materials, unfill_pixel_component, RigidBodyImageHandle,
 };
 
-use crate::{pixel::update_pixel_simulation, screen::Screen, SpawnWorlds};
+use crate::{
+    pixel::{
+        material::RemapMaterials,
+        streaming::{streaming_enabled, ChunkLoader},
+        update_pixel_simulation, GameCamera,
+    },
+    screen::Screen,
+    simulation::{plan_simulation_steps, simulation_running, SimulationControl},
+    SpawnWorlds,
+};
 
 pub struct SandEngineRigidPlugin;
 
@@ -35,54 +48,111 @@ impl Plugin for SandEngineRigidPlugin {
         app.insert_resource(RigidStorage {
             colliders: Vec::new(),
         })
-        .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(1.))
+        // Physics steps in the fixed update along with the pixel simulation, so both follow the simulation control
+        .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(1.).in_fixed_schedule())
         .add_plugins((
             TnuaRapier2dPlugin::new(FixedUpdate),
             TnuaControllerPlugin::new(FixedUpdate),
             TnuaCrouchEnforcerPlugin::new(FixedUpdate),
+            explosion::plugin,
             interaction::plugin,
         ))
         .add_systems(Startup, |mut cfg: ResMut<RapierConfiguration>| {
             cfg.gravity = Vec2::Y * -9.81;
         })
+        .init_resource::<PlayerControls>()
         .insert_resource(RigidBodyImageHandle { handle: None })
         .add_systems(Startup, load_rigidbody_image)
+        .add_systems(
+            Update,
+            remap_pixel_component_materials.in_set(RemapMaterials),
+        )
+        .add_systems(
+            FixedFirst,
+            follow_simulation_control
+                .after(plan_simulation_steps)
+                .run_if(in_state(Screen::Playing)),
+        )
         .add_systems(
             FixedUpdate.intern(),
+            // Controls are held back while paused, so motion does not build up and apply all at once on resume
             apply_platformer_controls
                 .in_set(TnuaUserControlsSystemSet)
-                .run_if(in_state(Screen::Playing)),
+                .run_if(in_state(Screen::Playing).and_then(simulation_running)),
         )
         .add_systems(
             FixedUpdate,
             (
                 fill_pixel_component.before(update_pixel_simulation),
                 unfill_pixel_component.after(update_pixel_simulation),
+                collapse_floating_islands,
                 chunk_collider_generation,
             )
                 .chain()
-                .run_if(in_state(Screen::Playing)),
+                .before(PhysicsSet::SyncBackend)
+                .run_if(in_state(Screen::Playing).and_then(simulation_running)),
+        )
+        // Chunks are streamed around the camera, so it moves in the fixed update for replays to load the same chunks
+        .add_systems(
+            FixedUpdate,
+            camera_follow_player
+                .after(PhysicsSet::Writeback)
+                .run_if(in_state(Screen::Playing).and_then(streaming_enabled)),
         );
     }
 }
 
+// Steps the physics pipeline as far as the pixel simulation is stepped in this fixed update
+// Each step of the pixel simulation gets its own substep, so a body moves the same distance per step at any speed
+fn follow_simulation_control(
+    control: Res<SimulationControl>,
+    time: Res<Time<Fixed>>,
+    mut config: ResMut<RapierConfiguration>,
+    mut timestep: ResMut<TimestepMode>,
+) {
+    let steps = control.steps();
+    config.physics_pipeline_active = steps > 0;
+    if steps > 0 {
+        *timestep = TimestepMode::Fixed {
+            dt: time.timestep().as_secs_f32() * steps as f32,
+            substeps: steps as usize,
+        };
+    }
+}
+
 // RigidStorage is a resource that stores a vector for each chunk that contains the entities of the colliders in that chunk
 #[derive(Resource)]
 pub struct RigidStorage {
-    // Static colliders generated from the pixel simulation
-    pub colliders: Vec<Option<Vec<Entity>>>,
+    // Static colliders generated from the pixel simulation, by the index of their chunk in the world's grid
+    // Slots keep the position of their chunk, sparse grids give the index of an unloaded chunk to the next one that is loaded
+    pub colliders: Vec<Option<(IVec2, Vec<Entity>)>>,
 }
 
+// Marker for the player controlled character
+#[derive(Component)]
+pub struct Player;
+
 pub fn spawn_rigid_world(
-    In(_config): In<SpawnWorlds>,
+    In(config): In<SpawnWorlds>,
     mut commands: Commands,
     mut rigid_storage: ResMut<RigidStorage>,
+    mut controls: ResMut<PlayerControls>,
 ) {
-    setup_physics_environment(&mut commands);
-    setup_player(&mut commands);
-
-    // Reset rigid storage
+    // Generated terrain has its own colliders and keeps going below the floor
+    if !config.terrain {
+        setup_physics_environment(&mut commands);
+    }
+    // The player drops onto generated terrain from near the top of the starting area
+    let player_height = if config.terrain {
+        config.world_size.y as f32 - 16.
+    } else {
+        10.
+    };
+    setup_player(&mut commands, Vec2::new(30., player_height));
+
+    // Reset rigid storage and the controls of the previous player
     rigid_storage.colliders.clear();
+    *controls = PlayerControls::default();
 }
 
 // Setting simple stage
@@ -94,15 +164,17 @@ fn setup_physics_environment(commands: &mut Commands) {
     cmd.insert(StateScoped(Screen::Playing));
 }
 
-fn setup_player(commands: &mut Commands) {
-    let mut cmd = commands.spawn_empty();
-    cmd.insert(TransformBundle::from_transform(Transform::from_xyz(
-        30.0, 10.0, 0.0,
-    )));
+fn setup_player(commands: &mut Commands, position: Vec2) {
+    let mut cmd = commands.spawn((Player, ChunkLoader { radius: 1 }));
+    cmd.insert(TransformBundle::from_transform(
+        Transform::from_translation(position.extend(0.)),
+    ));
     cmd.insert(VisibilityBundle::default());
 
     cmd.insert(RigidBody::Dynamic);
     cmd.insert(Collider::capsule_y(3.0, 1.0));
+    // Mass is read so that explosions can push the player
+    cmd.insert(ReadMassProperties::default());
     // For Rapier, an "IO" bundle needs to be added so that Tnua will have all the components
     // it needs to interact with Rapier.
     cmd.insert(TnuaRapier2dIOBundle::default());
@@ -146,3 +218,18 @@ fn setup_player(commands: &mut Commands) {
 
     cmd.insert(StateScoped(Screen::Playing));
 }
+
+// Moves the camera towards the player in streaming worlds, where the player can leave the starting area
+fn camera_follow_player(
+    player: Query<&Transform, (With<Player>, Without<GameCamera>)>,
+    mut camera: Query<&mut Transform, With<GameCamera>>,
+    time: Res<Time>,
+) {
+    let (Ok(player), Ok(mut camera)) = (player.get_single(), camera.get_single_mut()) else {
+        return;
+    };
+    let target = player.translation.truncate();
+    let current = camera.translation.truncate();
+    let followed = current.lerp(target, (time.delta_seconds() * 4.).min(1.));
+    camera.translation = followed.extend(camera.translation.z);
+}
@@ -55,6 +55,7 @@ pub fn add_non_dynamic_rigidbody(
         },
         RigidBody::Dynamic,
         ColliderMassProperties::default(),
+        ReadMassProperties::default(),
         Restitution::coefficient(0.7),
         StateScoped(Screen::Playing),
         RenderLayers::layer(1),
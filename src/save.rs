@@ -0,0 +1,311 @@
+//! Saving and loading of the current world, through the buttons of the pixel simulation controls
+//! The file holds the pixel world written by the simulation core, followed by the emitters, drains, dynamic bodies and the player
+//! Everything is read before anything is replaced, so a file that can't be loaded leaves the current world as it is
+
+use bevy::{
+    prelude::*,
+    render::{camera::ScalingMode, view::RenderLayers},
+};
+use bevy_rapier2d::prelude::Velocity;
+
+use crate::{
+    particles::particle::Particle,
+    pixel::{
+        cell::Cell,
+        chunk_generator,
+        emitter::{spawn_drain, spawn_emitter, CellDrain, CellEmitter},
+        generation::EmptyGenerator,
+        history::EditHistory,
+        material::MaterialRegistry,
+        streaming::ChunkStreaming,
+        world::PixelWorld,
+        GameCamera, LoadedChunks,
+    },
+    replay::Recording,
+    rigid::{
+        dynamic_entity::{DynamicPhysicsEntity, PixelComponent},
+        Player,
+    },
+    screen::Screen,
+    SpawnWorlds,
+};
+
+use sandengine_core::save::{SaveError, SaveReader, SaveWriter};
+
+// Where the world is saved, relative to the working directory
+const SAVE_PATH: &str = "saves/world.sand";
+
+// Dynamic bodies larger than this are assumed to come from a corrupt file
+const MAX_BODY_CELLS: usize = 1 << 20;
+
+pub(super) fn plugin(app: &mut App) {
+    app.add_event::<SaveWorld>()
+        .add_event::<LoadWorld>()
+        .add_systems(
+            Update,
+            (save_world, load_world).run_if(in_state(Screen::Playing)),
+        );
+}
+
+// Saves the current world to the save file
+#[derive(Event)]
+pub struct SaveWorld;
+
+// Replaces the current world with the one in the save file
+#[derive(Event)]
+pub struct LoadWorld;
+
+struct SavedBody {
+    position: Vec2,
+    angle: f32,
+    velocity: Velocity,
+    flip_y: bool,
+    size: UVec2,
+    cells: Vec<Cell>,
+}
+
+// Everything read from a save file
+struct Save {
+    streaming: bool,
+    terrain: bool,
+    world: PixelWorld,
+    player: Vec2,
+    emitters: Vec<CellEmitter>,
+    drains: Vec<CellDrain>,
+    bodies: Vec<SavedBody>,
+}
+
+fn save_world(
+    mut requests: EventReader<SaveWorld>,
+    sim: Query<&PixelWorld>,
+    config: Res<SpawnWorlds>,
+    materials: Res<MaterialRegistry>,
+    emitters: Query<&CellEmitter>,
+    drains: Query<&CellDrain>,
+    bodies: Query<(&Transform, &Velocity, &Sprite, &PixelComponent)>,
+    player: Query<&Transform, With<Player>>,
+) {
+    if requests.read().count() == 0 {
+        return;
+    }
+
+    let mut writer = SaveWriter::new(&materials);
+    writer.write_bool(config.streaming);
+    writer.write_bool(config.terrain);
+    sim.single().save(&mut writer);
+
+    let player = player
+        .get_single()
+        .map(|transform| transform.translation.truncate())
+        .unwrap_or_default();
+    writer.write_vec2(player);
+
+    writer.write_u32(emitters.iter().len() as u32);
+    for emitter in emitters.iter() {
+        writer.write_material(emitter.material);
+        writer.write_f32(emitter.rate);
+        writer.write_ivec2(emitter.area.min);
+        writer.write_ivec2(emitter.area.max);
+    }
+
+    writer.write_u32(drains.iter().len() as u32);
+    for drain in drains.iter() {
+        writer.write_ivec2(drain.area.min);
+        writer.write_ivec2(drain.area.max);
+    }
+
+    writer.write_u32(bodies.iter().len() as u32);
+    for (transform, velocity, sprite, pixel) in bodies.iter() {
+        writer.write_vec2(transform.translation.truncate());
+        writer.write_f32(transform.rotation.to_euler(EulerRot::XYZ).2);
+        writer.write_vec2(velocity.linvel);
+        writer.write_f32(velocity.angvel);
+        writer.write_bool(sprite.flip_y);
+        writer.write_uvec2(pixel.size);
+        writer.write_cells(&pixel.cells);
+    }
+
+    let result = writer.finish().and_then(|bytes| {
+        std::fs::create_dir_all("saves")?;
+        std::fs::write(SAVE_PATH, bytes)?;
+        Ok(())
+    });
+    match result {
+        Ok(()) => info!("Saved world to {SAVE_PATH}"),
+        Err(err) => error!("Could not save world to {SAVE_PATH}: {err}"),
+    }
+}
+
+fn read_save(bytes: &[u8], materials: &MaterialRegistry) -> Result<Save, SaveError> {
+    let mut reader = SaveReader::new(bytes, materials)?;
+    let streaming = reader.read_bool()?;
+    let terrain = reader.read_bool()?;
+
+    // The generator depends on the size of the saved world, which is only known once it is read
+    let mut world = PixelWorld::load(&mut reader, EmptyGenerator)?;
+    let saved_config = SpawnWorlds {
+        world_size: world.world_size,
+        chunk_amount: world.chunk_amount,
+        seed: world.seed,
+        streaming,
+        terrain,
+    };
+    world.set_generator(chunk_generator(&saved_config, materials));
+    if streaming {
+        world.chunks.make_sparse();
+    }
+
+    let player = reader.read_vec2()?;
+
+    let mut emitters = Vec::new();
+    for _ in 0..reader.read_u32()? {
+        emitters.push(CellEmitter {
+            material: reader.read_material()?,
+            rate: reader.read_f32()?,
+            area: IRect::from_corners(reader.read_ivec2()?, reader.read_ivec2()?),
+        });
+    }
+
+    let mut drains = Vec::new();
+    for _ in 0..reader.read_u32()? {
+        drains.push(CellDrain {
+            area: IRect::from_corners(reader.read_ivec2()?, reader.read_ivec2()?),
+        });
+    }
+
+    let mut bodies = Vec::new();
+    for _ in 0..reader.read_u32()? {
+        let position = reader.read_vec2()?;
+        let angle = reader.read_f32()?;
+        let velocity = Velocity {
+            linvel: reader.read_vec2()?,
+            angvel: reader.read_f32()?,
+        };
+        let flip_y = reader.read_bool()?;
+        let size = reader.read_uvec2()?;
+        let count = size.x as usize * size.y as usize;
+        if count > MAX_BODY_CELLS {
+            return Err(SaveError::Corrupt);
+        }
+        bodies.push(SavedBody {
+            position,
+            angle,
+            velocity,
+            flip_y,
+            size,
+            cells: reader.read_cells(count)?,
+        });
+    }
+
+    Ok(Save {
+        streaming,
+        terrain,
+        world,
+        player,
+        emitters,
+        drains,
+        bodies,
+    })
+}
+
+fn load_world(
+    mut commands: Commands,
+    mut requests: EventReader<LoadWorld>,
+    mut sim: Query<&mut PixelWorld>,
+    mut config: ResMut<SpawnWorlds>,
+    mut streaming: ResMut<ChunkStreaming>,
+    mut loaded_chunks: ResMut<LoadedChunks>,
+    mut history: ResMut<EditHistory>,
+    mut recording: ResMut<Recording>,
+    materials: Res<MaterialRegistry>,
+    mut images: ResMut<Assets<Image>>,
+    removed: Query<
+        Entity,
+        Or<(
+            With<CellEmitter>,
+            With<CellDrain>,
+            With<PixelComponent>,
+            With<Particle>,
+        )>,
+    >,
+    mut player: Query<&mut Transform, (With<Player>, Without<GameCamera>)>,
+    mut camera: Query<(&mut Transform, &mut OrthographicProjection), With<GameCamera>>,
+) {
+    if requests.read().count() == 0 {
+        return;
+    }
+
+    let save = std::fs::read(SAVE_PATH)
+        .map_err(SaveError::from)
+        .and_then(|bytes| read_save(&bytes, &materials));
+    let save = match save {
+        Ok(save) => save,
+        Err(err) => {
+            error!("Could not load world from {SAVE_PATH}: {err}");
+            return;
+        }
+    };
+
+    for entity in removed.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    // The chunk size may differ, so all chunk displays are created again
+    // Colliders are replaced by their system, as all chunks of the loaded world start out dirty
+    for (_, display) in loaded_chunks.chunks.drain() {
+        commands.entity(display).despawn();
+    }
+
+    let world_size = save.world.world_size;
+    *config = SpawnWorlds {
+        world_size,
+        chunk_amount: save.world.chunk_amount,
+        seed: save.world.seed,
+        streaming: save.streaming,
+        terrain: save.terrain,
+    };
+    streaming.enabled = save.streaming;
+    *sim.single_mut() = save.world;
+    history.clear();
+    // Replays start from freshly spawned worlds, which a loaded world is not
+    recording.stop();
+
+    if let Ok(mut transform) = player.get_single_mut() {
+        transform.translation = save.player.extend(transform.translation.z);
+    }
+    // The camera is moved to the player by streaming worlds, fixed worlds are viewed whole
+    if let Ok((mut transform, mut projection)) = camera.get_single_mut() {
+        projection.scaling_mode = ScalingMode::AutoMin {
+            min_width: world_size.x as f32,
+            min_height: world_size.y as f32,
+        };
+        if !save.streaming {
+            transform.translation = (world_size.as_vec2() / 2.).extend(transform.translation.z);
+        }
+    }
+
+    for emitter in save.emitters {
+        spawn_emitter(&mut commands, emitter, &materials);
+    }
+    for drain in save.drains {
+        spawn_drain(&mut commands, drain);
+    }
+    for body in save.bodies {
+        let Some(mut dpe) = DynamicPhysicsEntity::from_cells(
+            body.position,
+            body.size,
+            &body.cells,
+            body.flip_y,
+            &mut images,
+            &materials,
+        ) else {
+            continue;
+        };
+        dpe.sprite.transform.rotation = Quat::from_rotation_z(body.angle);
+        dpe.velocity = body.velocity;
+        commands
+            .spawn(dpe)
+            .insert((StateScoped(Screen::Playing), RenderLayers::layer(1)));
+    }
+
+    info!("Loaded world from {SAVE_PATH}");
+}
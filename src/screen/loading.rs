@@ -4,7 +4,7 @@
 use bevy::prelude::*;
 
 use super::Screen;
-use crate::{states::AppSet, ui::prelude::*};
+use crate::{pixel::material::MaterialRegistry, states::AppSet, ui::prelude::*};
 
 pub(super) fn plugin(app: &mut App) {
     app.add_systems(OnEnter(Screen::Loading), enter_loading);
@@ -55,8 +55,13 @@ fn tick_loading_timer(time: Res<Time>, mut timer: ResMut<LoadingTimer>) {
     timer.0.tick(time.delta());
 }
 
-fn check_loading_timer(timer: ResMut<LoadingTimer>, mut next_screen: ResMut<NextState<Screen>>) {
-    if timer.0.just_finished() {
+// Wait for both the timer and the material definitions, the pixel world can not be created without materials
+fn check_loading_timer(
+    timer: ResMut<LoadingTimer>,
+    materials: Res<MaterialRegistry>,
+    mut next_screen: ResMut<NextState<Screen>>,
+) {
+    if timer.0.finished() && materials.is_loaded() {
         next_screen.set(Screen::Playing);
     }
 }
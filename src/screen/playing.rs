@@ -2,12 +2,16 @@
 
 use bevy::{input::common_conditions::input_just_pressed, prelude::*, render::view::RenderLayers};
 
-use crate::{spawn_worlds, WorldSizes};
+use crate::{replay::PendingReplay, spawn_worlds, FixedWorldTerrain, WorldSizes};
 
 use super::Screen;
 
 pub(super) fn plugin(app: &mut App) {
-    app.add_systems(OnEnter(Screen::Playing), enter_playing);
+    // Replays spawn the worlds of their recording instead
+    app.add_systems(
+        OnEnter(Screen::Playing),
+        enter_playing.run_if(not(resource_exists::<PendingReplay>)),
+    );
 
     app.add_systems(
         Update,
@@ -19,8 +23,12 @@ pub(super) fn plugin(app: &mut App) {
     app.add_systems(OnEnter(Screen::Title), spawn_ui_camera);
 }
 
-fn enter_playing(mut commands: Commands, world_size: Res<State<WorldSizes>>) {
-    spawn_worlds(&mut commands, world_size)
+fn enter_playing(
+    mut commands: Commands,
+    world_size: Res<State<WorldSizes>>,
+    terrain: Res<FixedWorldTerrain>,
+) {
+    spawn_worlds(&mut commands, world_size, terrain)
 }
 
 fn return_to_title_screen(mut next_screen: ResMut<NextState<Screen>>) {
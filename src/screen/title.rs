@@ -3,25 +3,37 @@
 use bevy::prelude::*;
 
 use super::Screen;
-use crate::{ui::prelude::*, WorldSizes};
+#[cfg(not(target_family = "wasm"))]
+use crate::replay::{PendingReplay, RECORDING_PATH};
+use crate::{ui::prelude::*, FixedWorldTerrain, WorldSizes};
 
 pub(super) fn plugin(app: &mut App) {
     app.add_systems(OnEnter(Screen::Title), enter_title);
 
     app.register_type::<TitleAction>();
-    app.add_systems(Update, handle_title_action.run_if(in_state(Screen::Title)));
+    app.add_systems(
+        Update,
+        (handle_title_action, show_terrain_setting)
+            .chain()
+            .run_if(in_state(Screen::Title)),
+    );
 }
 
 #[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Reflect)]
 #[reflect(Component)]
 enum TitleAction {
     Play(WorldSizes),
+    /// Infinite worlds always have terrain, this only changes the fixed size worlds.
+    ToggleTerrain,
+    /// Recordings are read from the working directory, which the web build does not have.
+    #[cfg(not(target_family = "wasm"))]
+    Replay,
     /// Exit doesn't work well with embedded applications.
     #[cfg(not(target_family = "wasm"))]
     Exit,
 }
 
-fn enter_title(mut commands: Commands) {
+fn enter_title(mut commands: Commands, terrain: Res<FixedWorldTerrain>) {
     commands
         .ui_root()
         .insert(StateScoped(Screen::Title))
@@ -36,15 +48,30 @@ fn enter_title(mut commands: Commands) {
             children
                 .button("Play (Huge World)")
                 .insert(TitleAction::Play(WorldSizes::Large));
+            children
+                .button("Play (Wide World)")
+                .insert(TitleAction::Play(WorldSizes::Wide));
+            children
+                .button("Play (Infinite World)")
+                .insert(TitleAction::Play(WorldSizes::Infinite));
+            children
+                .button(terrain_label(*terrain))
+                .insert(TitleAction::ToggleTerrain);
 
             #[cfg(not(target_family = "wasm"))]
+            children
+                .button("Replay Recording")
+                .insert(TitleAction::Replay);
+            #[cfg(not(target_family = "wasm"))]
             children.button("Exit").insert(TitleAction::Exit);
         });
 }
 
 fn handle_title_action(
+    #[cfg(not(target_family = "wasm"))] mut commands: Commands,
     mut next_screen: ResMut<NextState<Screen>>,
     mut next_world_size: ResMut<NextState<WorldSizes>>,
+    mut terrain: ResMut<FixedWorldTerrain>,
     mut button_query: InteractionQuery<&TitleAction>,
     #[cfg(not(target_family = "wasm"))] mut app_exit: EventWriter<AppExit>,
 ) {
@@ -56,6 +83,19 @@ fn handle_title_action(
                     next_world_size.set(*size);
                 }
 
+                TitleAction::ToggleTerrain => {
+                    terrain.0 = !terrain.0;
+                }
+
+                #[cfg(not(target_family = "wasm"))]
+                TitleAction::Replay => {
+                    commands.insert_resource(PendingReplay {
+                        path: RECORDING_PATH.into(),
+                        exit_when_finished: false,
+                    });
+                    next_screen.set(Screen::Loading);
+                }
+
                 #[cfg(not(target_family = "wasm"))]
                 TitleAction::Exit => {
                     app_exit.send(AppExit::Success);
@@ -64,3 +104,29 @@ fn handle_title_action(
         }
     }
 }
+
+fn terrain_label(terrain: FixedWorldTerrain) -> String {
+    let setting = if terrain.0 { "On" } else { "Off" };
+    format!("Terrain: {setting}")
+}
+
+/// Updates the text of the terrain button once it has been toggled.
+fn show_terrain_setting(
+    terrain: Res<FixedWorldTerrain>,
+    buttons: Query<(&TitleAction, &Children)>,
+    mut texts: Query<&mut Text>,
+) {
+    if !terrain.is_changed() {
+        return;
+    }
+    for (action, children) in &buttons {
+        if *action != TitleAction::ToggleTerrain {
+            continue;
+        }
+        for child in children {
+            if let Ok(mut text) = texts.get_mut(*child) {
+                text.sections[0].value = terrain_label(*terrain);
+            }
+        }
+    }
+}
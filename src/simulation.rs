@@ -0,0 +1,95 @@
+//! Controls for how fast the simulation runs, shared by the pixel simulation, particles and physics
+//! Every fixed update works out how many simulation steps to run, and each part of the simulation runs that many steps
+//! This keeps the rigid bodies and the pixel world in step with each other when paused, single stepped or sped up
+
+use bevy::{input::common_conditions::input_just_pressed, prelude::*};
+
+use crate::screen::Screen;
+
+// Fastest speed the simulation can be set to, in steps per fixed update
+pub const MAX_SIMULATION_SPEED: f32 = 8.;
+
+pub(super) fn plugin(app: &mut App) {
+    app.init_resource::<SimulationControl>()
+        .add_systems(OnEnter(Screen::Playing), reset_simulation_control)
+        .add_systems(
+            FixedFirst,
+            plan_simulation_steps.run_if(in_state(Screen::Playing)),
+        )
+        .add_systems(
+            Update,
+            (
+                toggle_pause.run_if(input_just_pressed(KeyCode::KeyP)),
+                request_step.run_if(input_just_pressed(KeyCode::Period)),
+            )
+                .run_if(in_state(Screen::Playing)),
+        );
+}
+
+// Pause, single step and speed of the simulation
+#[derive(Resource, Debug)]
+pub struct SimulationControl {
+    pub paused: bool,
+    // Simulation steps per fixed update, values below 1 skip updates and values above 1 run several steps in one
+    pub speed: f32,
+    // Runs one step on the next fixed update while paused
+    pub(crate) step_requested: bool,
+    // Fraction of a step carried over to the next fixed update
+    accumulated: f32,
+    // Steps to run in the current fixed update
+    steps: u32,
+}
+
+impl Default for SimulationControl {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            speed: 1.,
+            step_requested: false,
+            accumulated: 0.,
+            steps: 0,
+        }
+    }
+}
+
+impl SimulationControl {
+    // Runs a single step while paused
+    pub fn step(&mut self) {
+        self.step_requested = true;
+    }
+
+    // How many steps the simulation runs in the current fixed update
+    pub fn steps(&self) -> u32 {
+        self.steps
+    }
+}
+
+// Run condition for systems which should only run on fixed updates that step the simulation
+pub fn simulation_running(control: Res<SimulationControl>) -> bool {
+    control.steps > 0
+}
+
+// Runs first in every fixed update, before anything is simulated
+pub fn plan_simulation_steps(mut control: ResMut<SimulationControl>) {
+    control.steps = if control.paused {
+        std::mem::take(&mut control.step_requested) as u32
+    } else {
+        control.step_requested = false;
+        control.accumulated += control.speed.clamp(0., MAX_SIMULATION_SPEED);
+        let steps = control.accumulated.floor();
+        control.accumulated -= steps;
+        steps as u32
+    };
+}
+
+fn reset_simulation_control(mut control: ResMut<SimulationControl>) {
+    *control = SimulationControl::default();
+}
+
+fn toggle_pause(mut control: ResMut<SimulationControl>) {
+    control.paused = !control.paused;
+}
+
+fn request_step(mut control: ResMut<SimulationControl>) {
+    control.step();
+}